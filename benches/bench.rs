@@ -20,3 +20,15 @@ fn lambda_battle(b: &mut Bencher) {
 fn tube_battle(b: &mut Bencher) {
     b.iter(|| arena::sample_battle("tube.json"));
 }
+
+#[bench]
+fn oxford_10000_edge_weight(b: &mut Bencher) {
+    let game = arena::sample_game("oxford-10000.json");
+    b.iter(|| game.play_edge_weight());
+}
+
+#[bench]
+fn edinburgh_10000_edge_weight(b: &mut Bencher) {
+    let game = arena::sample_game("edinburgh-10000.json");
+    b.iter(|| game.play_edge_weight());
+}
@@ -6,6 +6,8 @@ extern crate log;
 extern crate serde_derive;
 #[macro_use]
 extern crate quick_error;
+#[macro_use]
+extern crate serde_json;
 extern crate base64;
 extern crate bincode;
 extern crate chrono;
@@ -13,6 +15,5 @@ extern crate pbr;
 extern crate rand;
 extern crate rayon;
 extern crate serde;
-extern crate serde_json;
 
 pub mod punter;
@@ -5,10 +5,29 @@ extern crate log;
 extern crate env_logger;
 extern crate loggerv;
 extern crate clap;
+extern crate serde_json;
 
-use clap::{App, Arg, SubCommand};
-use icfp2017::punter::arena;
+use clap::{App, Arg, ArgMatches, SubCommand};
+use icfp2017::punter::arena::{self, ArenaOutputs};
+use icfp2017::punter::compare;
+use icfp2017::punter::gen_map;
+use icfp2017::punter::graph_io::{self, GraphFormat};
+use icfp2017::punter::map_info;
+use icfp2017::punter::parallel;
+use icfp2017::punter::params::EdgeWeightParams;
 use icfp2017::punter::play;
+use icfp2017::punter::rating::Ratings;
+use icfp2017::punter::serve::{self, LiveFeed};
+use icfp2017::punter::svg;
+use icfp2017::punter::tui::{LiveView, Pace};
+use icfp2017::punter::tune;
+use icfp2017::punter::validate;
+use std::fs::File;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
 fn build_cli() -> App<'static, 'static> {
     App::new("icfp2017")
@@ -16,13 +35,57 @@ fn build_cli() -> App<'static, 'static> {
         .arg(Arg::with_name("v").short("v").multiple(true).help(
             "Sets the level of verbosity",
         ))
+        .arg(Arg::with_name("params").long("params").takes_value(true).help(
+            "Reads EdgeWeight parameters from a JSON file",
+        ))
+        .arg(
+            Arg::with_name("param")
+                .long("param")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .help("Sets an EdgeWeight parameter, e.g. --param contention_penalty=0.5"),
+        )
+        .arg(Arg::with_name("record").long("record").takes_value(true).help(
+            "Records battles for the visualizer into this directory",
+        ))
+        .arg(Arg::with_name("ratings").long("ratings").takes_value(true).help(
+            "Updates the ratings in this file with every battle",
+        ))
+        .arg(Arg::with_name("export").long("export").takes_value(true).help(
+            "Exports per-game results and a summary into this directory as CSV and JSON",
+        ))
+        .arg(Arg::with_name("threads").long("threads").takes_value(true).help(
+            "Sets the number of threads used to compute a move (default: 1)",
+        ))
         .subcommand(SubCommand::with_name("internal-arena"))
         .subcommand(SubCommand::with_name("online").arg(
             Arg::with_name("port").takes_value(true).required(true),
         ))
-        .subcommand(SubCommand::with_name("arena").arg(
-            Arg::with_name("bot").multiple(true),
-        ))
+        .subcommand(
+            SubCommand::with_name("arena")
+                .arg(
+                    Arg::with_name("config")
+                        .long("config")
+                        .takes_value(true)
+                        .help("Reads the arena setup from a JSON file (default: arena/offline.json)"),
+                )
+                .arg(Arg::with_name("bot").multiple(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("serve")
+                .about("Serves the visualizer and recorded battles (--record, or visualizer/data) on localhost")
+                .arg(Arg::with_name("port").long("port").default_value("8000"))
+                .arg(
+                    Arg::with_name("map")
+                        .short("m")
+                        .long("map")
+                        .takes_value(true)
+                        .requires("bot")
+                        .help("Plays a battle on this map, which is streamed live"),
+                )
+                .arg(Arg::with_name("bot").multiple(true)),
+        )
         .subcommand(
             SubCommand::with_name("single-match")
                 .arg(
@@ -38,8 +101,181 @@ fn build_cli() -> App<'static, 'static> {
                         .long("games")
                         .default_value("1"),
                 )
+                .arg(Arg::with_name("tui").long("tui").help("Shows the first battle live"))
+                .arg(Arg::with_name("step").long("step").requires("tui").help("Waits for enter after each turn"))
+                .arg(
+                    Arg::with_name("delay")
+                        .long("delay")
+                        .default_value("50")
+                        .help("Milliseconds between turns in the live view"),
+                )
                 .arg(Arg::with_name("bot").multiple(true)),
         )
+        .subcommand(
+            SubCommand::with_name("gen-map")
+                .about("Generates a random map")
+                .arg(
+                    Arg::with_name("family")
+                        .required(true)
+                        .possible_values(gen_map::MapFamily::names()),
+                )
+                .arg(
+                    Arg::with_name("size")
+                        .short("n")
+                        .long("size")
+                        .default_value("100"),
+                )
+                .arg(Arg::with_name("mines").long("mines").default_value("4"))
+                .arg(Arg::with_name("seed").long("seed").default_value("0"))
+                .arg(
+                    Arg::with_name("out")
+                        .short("o")
+                        .long("out")
+                        .takes_value(true)
+                        .help("Writes the map to a file instead of stdout"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("export")
+                .about("Exports a map or a recorded battle to DOT or GraphML")
+                .arg(Arg::with_name("input").required(true))
+                .arg(
+                    Arg::with_name("format")
+                        .long("format")
+                        .possible_values(&["dot", "graphml"])
+                        .default_value("dot"),
+                )
+                .arg(
+                    Arg::with_name("turn")
+                        .long("turn")
+                        .takes_value(true)
+                        .help("Shows a recorded battle after this many moves"),
+                )
+                .arg(Arg::with_name("out").short("o").long("out").takes_value(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("import")
+                .about("Imports a GraphML (.graphml) or edge list file as a map")
+                .arg(Arg::with_name("input").required(true))
+                .arg(
+                    Arg::with_name("mine")
+                        .long("mine")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("mines")
+                        .long("mines")
+                        .default_value("4")
+                        .help("Number of random mines if the file has none"),
+                )
+                .arg(Arg::with_name("seed").long("seed").default_value("0"))
+                .arg(Arg::with_name("out").short("o").long("out").takes_value(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("map-info")
+                .about("Reports the structure of a map")
+                .arg(Arg::with_name("map").required(true))
+                .arg(Arg::with_name("json").long("json").help("Prints JSON")),
+        )
+        .subcommand(
+            SubCommand::with_name("render")
+                .about("Renders a recorded battle as SVG")
+                .arg(Arg::with_name("input").required(true))
+                .arg(
+                    Arg::with_name("turn")
+                        .long("turn")
+                        .takes_value(true)
+                        .help("Shows the battle after this many moves"),
+                )
+                .arg(Arg::with_name("out").short("o").long("out").takes_value(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("compare")
+                .about("Compares bots statistically on games.json written by --export")
+                .arg(Arg::with_name("games").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("leaderboard")
+                .about("Shows the ratings kept by --ratings")
+                .arg(Arg::with_name("ratings").required(true)),
+        )
+        .subcommand(
+            SubCommand::with_name("validate-map")
+                .about("Reports problems of a map")
+                .arg(Arg::with_name("map").required(true))
+                .arg(
+                    Arg::with_name("normalize")
+                        .long("normalize")
+                        .takes_value(true)
+                        .help("Writes the map without its problems to a file"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("tune")
+                .about("Tunes EdgeWeight parameters (initial values from --params/--param)")
+                .arg(
+                    Arg::with_name("map")
+                        .short("m")
+                        .long("map")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("opponent")
+                        .long("opponent")
+                        .takes_value(true)
+                        .multiple(true)
+                        .number_of_values(1),
+                )
+                .arg(
+                    Arg::with_name("iterations")
+                        .short("n")
+                        .long("iterations")
+                        .default_value("50"),
+                )
+                .arg(
+                    Arg::with_name("games")
+                        .short("g")
+                        .long("games")
+                        .default_value("8"),
+                )
+                .arg(Arg::with_name("seed").long("seed").default_value("0"))
+                .arg(
+                    Arg::with_name("out")
+                        .short("o")
+                        .long("out")
+                        .default_value("tune"),
+                ),
+        )
+}
+
+fn edge_weight_params(matches: &ArgMatches) -> Option<EdgeWeightParams> {
+    if !matches.is_present("params") && !matches.is_present("param") {
+        return None;
+    }
+    let mut params = match matches.value_of("params") {
+        Some(path) => EdgeWeightParams::from_file(path).expect("failed to read params"),
+        None => Default::default(),
+    };
+    if let Some(values) = matches.values_of("param") {
+        for v in values {
+            params.set_from_str(v).expect("invalid param");
+        }
+    }
+    Some(params)
+}
+
+fn write_output(path: Option<&str>, s: &str) {
+    match path {
+        Some(path) => {
+            let mut f = File::create(path).expect("failed to create the output");
+            f.write_all(s.as_bytes()).expect("failed to write the output");
+        }
+        None => println!("{}", s),
+    }
 }
 
 fn main() {
@@ -57,28 +293,126 @@ fn main() {
         }
     }
     debug!("Hello");
+    let params = edge_weight_params(&matches);
+    if let Some(threads) = matches.value_of("threads") {
+        parallel::set_threads(threads.parse().expect("invalid threads")).expect("failed to set threads");
+    }
+    let record_dir = matches.value_of("record").map(PathBuf::from);
+    let outputs = ArenaOutputs {
+        record_dir: record_dir.clone(),
+        ratings: matches.value_of("ratings").map(PathBuf::from),
+        export_dir: matches.value_of("export").map(PathBuf::from),
+    };
     if let Some(_) = matches.subcommand_matches("internal-arena") {
         debug!(">>> internal-arena_run");
-        arena::internal_arena_run().expect("internal-arena failes");
+        arena::internal_arena_run(params, outputs).expect("internal-arena failes");
     } else if let Some(sub) = matches.subcommand_matches("arena") {
-        arena::arena_run(sub.values_of("bot").unwrap().collect::<Vec<_>>()).expect("offline_arena_run fails");
+        arena::arena_run(
+            sub.values_of("bot").map(|bots| bots.collect()).unwrap_or_default(),
+            sub.value_of("config").map(Path::new),
+            outputs,
+        ).expect("offline_arena_run fails");
     } else if let Some(sub) = matches.subcommand_matches("single-match") {
         arena::single_match(
             sub.values_of("bot").unwrap().collect::<Vec<_>>(),
             sub.value_of("map").unwrap(),
             sub.value_of("games").unwrap().parse().unwrap(),
+            if sub.is_present("tui") {
+                let view = LiveView::new(if sub.is_present("step") {
+                    Pace::Step
+                } else {
+                    Pace::FastForward(Duration::from_millis(sub.value_of("delay").unwrap().parse().unwrap()))
+                });
+                Some(Arc::new(Mutex::new(view)))
+            } else {
+                None
+            },
+            outputs,
         ).expect("single-mach fails");
+    } else if let Some(sub) = matches.subcommand_matches("serve") {
+        let feed = Arc::new(Mutex::new(LiveFeed::new()));
+        let record_dir = record_dir.unwrap_or_else(|| PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/visualizer/data")));
+        if let Some(map) = sub.value_of("map") {
+            let bots = sub.values_of("bot").unwrap().map(String::from).collect::<Vec<_>>();
+            let (map, feed) = (map.to_string(), feed.clone());
+            let outputs = ArenaOutputs {
+                record_dir: Some(record_dir.clone()),
+                ..outputs
+            };
+            thread::spawn(move || arena::single_match(bots, map, 1, Some(feed), outputs).expect("single-match fails"));
+        }
+        let server = serve::Server {
+            visualizer_dir: PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/visualizer")),
+            record_dir,
+            feed,
+        };
+        serve::serve_run(sub.value_of("port").unwrap().parse().expect("invalid port"), server).expect("serve fails");
+    } else if let Some(sub) = matches.subcommand_matches("gen-map") {
+        let generator = gen_map::MapGenerator {
+            family: sub.value_of("family").unwrap().parse().unwrap(),
+            size: sub.value_of("size").unwrap().parse().unwrap(),
+            mines: sub.value_of("mines").unwrap().parse().unwrap(),
+            seed: sub.value_of("seed").unwrap().parse().unwrap(),
+        };
+        gen_map::gen_map_run(&generator, sub.value_of("out")).expect("gen-map fails");
+    } else if let Some(sub) = matches.subcommand_matches("export") {
+        let format = match sub.value_of("format").unwrap() {
+            "graphml" => GraphFormat::GraphML,
+            _ => GraphFormat::Dot,
+        };
+        let turn = sub.value_of("turn").map(|t| t.parse().expect("invalid turn"));
+        let graph = graph_io::export_run(sub.value_of("input").unwrap(), format, turn).expect("export fails");
+        write_output(sub.value_of("out"), &graph);
+    } else if let Some(sub) = matches.subcommand_matches("import") {
+        let map = graph_io::import_run(
+            sub.value_of("input").unwrap(),
+            sub.values_of("mine")
+                .map(|mines| mines.map(|m| m.parse().expect("invalid mine")).collect())
+                .unwrap_or_default(),
+            sub.value_of("mines").unwrap().parse().unwrap(),
+            sub.value_of("seed").unwrap().parse().unwrap(),
+        ).expect("import fails");
+        write_output(sub.value_of("out"), &serde_json::to_string(&map).unwrap());
+    } else if let Some(sub) = matches.subcommand_matches("map-info") {
+        map_info::map_info_run(sub.value_of("map").unwrap(), sub.is_present("json")).expect("map-info fails");
+    } else if let Some(sub) = matches.subcommand_matches("render") {
+        let turn = sub.value_of("turn").map(|t| t.parse().expect("invalid turn"));
+        let svg = svg::render_run(sub.value_of("input").unwrap(), turn).expect("render fails");
+        write_output(sub.value_of("out"), &svg);
+    } else if let Some(sub) = matches.subcommand_matches("compare") {
+        print!("{}", compare::compare_run(sub.value_of("games").unwrap()).expect("compare fails"));
+    } else if let Some(sub) = matches.subcommand_matches("leaderboard") {
+        print!("{}", Ratings::load(sub.value_of("ratings").unwrap()).expect("invalid ratings"));
+    } else if let Some(sub) = matches.subcommand_matches("validate-map") {
+        validate::validate_map_run(sub.value_of("map").unwrap(), sub.value_of("normalize")).expect("validate-map fails");
+    } else if let Some(sub) = matches.subcommand_matches("tune") {
+        let tuner = tune::Tuner {
+            maps: match sub.values_of("map") {
+                Some(maps) => maps.map(PathBuf::from).collect(),
+                None => tune::default_maps(),
+            },
+            opponents: match sub.values_of("opponent") {
+                Some(opponents) => opponents.map(|s| s.to_string()).collect(),
+                None => vec!["EdgeWeight".to_string(), "Greedy".to_string()],
+            },
+            games_per_map: sub.value_of("games").unwrap().parse().unwrap(),
+            iterations: sub.value_of("iterations").unwrap().parse().unwrap(),
+            seed: sub.value_of("seed").unwrap().parse().unwrap(),
+            output: PathBuf::from(sub.value_of("out").unwrap()),
+            initial: params.unwrap_or_default(),
+        };
+        tuner.run().expect("tune fails");
     } else if let Some(sub) = matches.subcommand_matches("online") {
         debug!(">>> online_run");
         let game = play::online_run(&format!(
             "{}:{}",
             "punter.inf.ed.ac.uk",
             sub.value_of("port").unwrap()
-        )).expect("game fails");
+        ), params.unwrap_or_default()).expect("game fails");
         game.print_summary();
     } else {
         debug!(">>> offline_run");
-        play::offline_run().expect("offline_run fails");
+        play::offline_run(params.unwrap_or_default()).expect("offline_run fails");
     }
     debug!("Bye");
 }
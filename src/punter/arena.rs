@@ -1,10 +1,17 @@
 use chrono;
 use pbr;
+use punter::arena_config::{ArenaConfig, BotConfig};
 use punter::bot::{self, Bot, BotMaker};
+use punter::budget::{MOVE_TIME_LIMIT_MILLIS, millis};
+use punter::compare;
+use punter::export::{self, GameRow};
 use punter::game::{Game, Strategy};
+use punter::params::EdgeWeightParams;
 use punter::prelude::*;
 use punter::protocol::*;
-use rand::{self, Rng};
+use punter::rating::Ratings;
+use punter::validate;
+use rand::{self, Rng, SeedableRng, StdRng};
 use rayon::prelude::*;
 use serde_json;
 use std;
@@ -15,7 +22,7 @@ use std::io::prelude::*;
 use std::path::{Path, PathBuf};
 use std::sync::{Arc, Mutex};
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct BotStat {
     pub point: Vec<usize>,
     pub score: Vec<i64>,
@@ -46,9 +53,20 @@ impl fmt::Display for BotStat {
     }
 }
 
+impl BotStat {
+    fn add(&mut self, result: &PunterScore) {
+        self.point.push(result.point);
+        self.score.push(result.score);
+        self.move_count += result.move_count;
+        self.consumed_time += result.consumed_time;
+    }
+}
+
 #[derive(Debug)]
 pub struct ArenaStats {
     pub stats: BTreeMap<String, BotStat>,
+    // The same, by settings label (see Settings's Display) and then by bot.
+    pub by_settings: BTreeMap<String, BTreeMap<String, BotStat>>,
 }
 
 impl fmt::Display for ArenaStats {
@@ -56,80 +74,119 @@ impl fmt::Display for ArenaStats {
         for (name, bot_stat) in self.stats.iter() {
             writeln!(f, "{:>32}, {}", name, bot_stat)?;
         }
+        // The breakdown only tells something if there are several combinations.
+        if self.by_settings.len() > 1 {
+            for (settings, stats) in self.by_settings.iter() {
+                writeln!(f, "settings: {}", settings)?;
+                for (name, bot_stat) in stats.iter() {
+                    writeln!(f, "{:>32}, {}", name, bot_stat)?;
+                }
+            }
+        }
         Ok(())
     }
 }
 
 impl ArenaStats {
     fn new() -> Self {
-        ArenaStats { stats: Default::default() }
+        ArenaStats {
+            stats: Default::default(),
+            by_settings: Default::default(),
+        }
     }
 }
 
 impl ArenaStats {
     fn add(&mut self, result: PunterScore) {
-        let bot = &mut self.stats.entry(result.bot_name).or_insert(BotStat {
-            point: vec![],
-            score: vec![],
-            move_count: 0,
-            consumed_time: Default::default(),
-        });
-        bot.point.push(result.point);
-        bot.score.push(result.score);
-        bot.move_count += result.move_count;
-        bot.consumed_time += result.consumed_time;
+        self.stats.entry(result.bot_name.clone()).or_insert_with(Default::default).add(&result);
+        self.by_settings
+            .entry(result.settings.clone())
+            .or_insert_with(Default::default)
+            .entry(result.bot_name.clone())
+            .or_insert_with(Default::default)
+            .add(&result);
+    }
+}
+
+// Receives the events of battles. Battles run in parallel, so each event carries the number of its battle.
+pub trait Listener: Send {
+    fn start(&mut self, _battle: usize, _map: &Map, _names: &[String]) {}
+    fn play(&mut self, battle: usize, mov: &Move);
+    fn stop(&mut self, _battle: usize, _scores: &[i64]) {}
+    // Listeners which slow battles down do it through a pacer, so that they do not hold the lock meanwhile.
+    fn pacer(&self) -> Option<Arc<Pacer>> {
+        None
     }
 }
 
-struct Listener {
+// Called by a battle after each move, without the listener locked. Other battles go on while one is paced.
+pub trait Pacer: Send + Sync {
+    fn pace(&self, battle: usize);
+}
+
+pub struct ProgressListener {
     bar: pbr::ProgressBar<std::io::Stdout>,
 }
 
-impl Listener {
-    fn inc(&mut self) {
+impl Listener for ProgressListener {
+    fn play(&mut self, _battle: usize, _mov: &Move) {
         self.bar.inc();
     }
 }
 
 struct Battle {
+    id: usize,
     map: Map,
     settings: Settings,
     bots: Vec<Box<Bot>>,
     listener: Option<Arc<Mutex<Listener>>>,
+    record_dir: Option<PathBuf>,
 }
 
 impl Battle {
-    pub fn new(map: Map, settings: Settings, bots: Vec<Box<Bot>>, listener: Option<Arc<Mutex<Listener>>>) -> Battle {
+    pub fn new(
+        id: usize,
+        map: Map,
+        settings: Settings,
+        bots: Vec<Box<Bot>>,
+        listener: Option<Arc<Mutex<Listener>>>,
+        record_dir: Option<PathBuf>,
+    ) -> Battle {
         Battle {
+            id,
             map,
             bots,
             settings,
             listener,
+            record_dir,
         }
     }
 
     fn run(self) -> PunterResult<Vec<PunterScore>> {
         let Battle {
+            id,
             map,
             settings,
             bots,
-            mut listener,
+            listener,
+            record_dir,
         } = self;
         let punters = bots.len();
 
         struct Punter {
             id: PunterId,
             bot: Box<Bot>,
-            game: Game,
-            #[allow(dead_code)]
-            futures: Option<Vec<Future>>,
             last_move: Move,
             state: EncodedGameState,
             score: i64,
             move_count: u64,
             consumed_time: std::time::Duration,
+            errors: usize,
+            timeouts: usize,
         };
 
+        let mut referee = Referee::new(&map, &settings, punters);
+
         // Setup phase
         let mut punters = bots.into_iter()
             .enumerate()
@@ -140,22 +197,38 @@ impl Battle {
                     map: map.clone(),
                     settings: Some(settings.clone()),
                 };
-                let rep = bot.setup(setup.clone())?;
+                let rep = bot.setup(setup)?;
+                referee.bid(punter_id, rep.futures);
                 Ok(Punter {
                     id: punter_id,
                     bot: bot,
-                    game: setup.into(),
-                    futures: rep.futures,
                     last_move: Move::Pass { pass: Pass { punter: punter_id } },
                     state: rep.state,
                     score: 0,
                     move_count: 0,
                     consumed_time: std::time::Duration::new(0, 0),
+                    errors: 0,
+                    timeouts: 0,
                 })
             })
             .collect::<Result<Vec<Punter>, PunterError>>()?;
 
-        let mut moves = vec![];
+        let mut pacer = None;
+        if let Some(ref listener) = listener {
+            let names = punters.iter().map(|p| p.bot.name()).collect::<Vec<_>>();
+            let mut listener = listener.lock().unwrap();
+            listener.start(id, &map, &names);
+            pacer = listener.pacer();
+        }
+
+        let mut recorder = record_dir.as_ref().map(|_| {
+            Recorder::new(
+                &map,
+                &settings,
+                punters.iter().map(|p| p.bot.name()).collect(),
+                referee.futures.clone(),
+            )
+        });
 
         // Gameplay phase
         let turns = map.rivers.len();
@@ -168,35 +241,55 @@ impl Battle {
                 state: punter.state.clone(),
             };
 
-            if let Some(ref mut listener) = listener {
-                let mut li = listener.lock().unwrap();
-                li.inc();
-            }
-
             let now = std::time::Instant::now();
-            let mov: Move = match punter.bot.play(gameplay) {
+            let (mut mov, error): (Move, Option<String>) = match punter.bot.play(gameplay) {
                 Ok(rep) => {
                     let state = rep.state();
                     punter.state = state;
-                    rep.into()
+                    (rep.into(), None)
                 }
-                Err(_) => {
-                    warn!("punter error: {}", punter.id);
-                    Move::Pass { pass: Pass { punter: punter.id } }
+                Err(e) => {
+                    warn!("punter error: {}: {}", punter.id, e);
+                    (Move::Pass { pass: Pass { punter: punter.id } }, Some(e.to_string()))
                 }
             };
+            let elapsed = now.elapsed();
             punter.move_count += 1;
-            punter.consumed_time += now.elapsed();
+            punter.consumed_time += elapsed;
+            if error.is_some() {
+                punter.errors += 1;
+            }
+            // The time limit is not enforced, but its violations are counted.
+            if millis(elapsed) > MOVE_TIME_LIMIT_MILLIS {
+                punter.timeouts += 1;
+            }
+            let illegal = referee.judge(punter.id, &mov).err().map(|reason| {
+                format!("{}: {}", reason, serde_json::to_string(&mov).unwrap())
+            });
+            if let Some(ref illegal) = illegal {
+                warn!("punter {}: illegal move, played as a pass: {}", punter.id, illegal);
+                punter.errors += 1;
+                mov = Move::Pass { pass: Pass { punter: punter.id } };
+            }
+            referee.apply(mov.clone());
+            if let Some(ref mut recorder) = recorder {
+                let scores = (0..referee.futures.len()).map(|p| referee.score(p)).collect();
+                recorder.record(punter.id, &mov, elapsed, error, illegal, scores);
+            }
 
             info!("move: {:?}", mov);
-            punter.game.apply_move(mov.clone());
-            punter.last_move = mov.clone();
-            moves.push(mov);
+            if let Some(ref listener) = listener {
+                listener.lock().unwrap().play(id, &mov);
+            }
+            if let Some(ref pacer) = pacer {
+                pacer.pace(id);
+            }
+            punter.last_move = mov;
         }
 
         // Scoring phase
         for p in punters.iter_mut() {
-            p.score = p.game.score(p.game.me);
+            p.score = referee.score(p.id);
         }
 
         for i in 0..punters.len() {
@@ -223,8 +316,12 @@ impl Battle {
             punter.last_move = Move::Pass { pass: Pass { punter: punter_index } };
         }
 
-        if std::env::var("MY_ICFP2017_RECORD_BATTLE").is_ok() {
-            VisGraph::new(map, moves).write_vis_graph_json();
+        if let Some(ref listener) = listener {
+            listener.lock().unwrap().stop(id, &punters.iter().map(|p| p.score).collect::<Vec<_>>());
+        }
+
+        if let (Some(recorder), Some(dir)) = (recorder, record_dir) {
+            recorder.vis_graph.write(dir)?;
         }
 
         let mut scores = punters.iter().map(|p| -p.score).collect::<Vec<i64>>();
@@ -234,11 +331,16 @@ impl Battle {
                 .iter()
                 .map(|p| {
                     PunterScore {
+                        battle: id,
+                        seat: p.id,
                         bot_name: p.bot.name(),
+                        settings: settings.to_string(),
                         point: punters.len() - scores.binary_search(&-p.score).unwrap(),
                         score: p.score,
                         move_count: p.move_count,
                         consumed_time: p.consumed_time,
+                        errors: p.errors,
+                        timeouts: p.timeouts,
                     }
                 })
                 .collect(),
@@ -246,115 +348,485 @@ impl Battle {
     }
 }
 
-struct PunterScore {
-    bot_name: String,
-    point: usize,
-    score: i64,
-    move_count: u64,
-    consumed_time: std::time::Duration,
+pub struct PunterScore {
+    pub battle: usize,
+    pub seat: PunterId,
+    pub bot_name: String,
+    // The label of the settings the game was played with.
+    pub settings: String,
+    pub point: usize,
+    pub score: i64,
+    pub move_count: u64,
+    pub consumed_time: std::time::Duration,
+    // Moves the bot failed to make, and moves over the time limit.
+    pub errors: usize,
+    pub timeouts: usize,
+}
+
+pub struct Arena {
+    pub bot_makers: Vec<BotMaker>,
+    pub maps: Vec<PathBuf>,
+    pub games_per_map: usize,
+    // If set, each game seats this many bots drawn from bot_makers.
+    pub punters: Option<usize>,
+    // Each game is played once with each settings combination, in the same seat order.
+    pub settings: Vec<Settings>,
+    // If set, seat orders are reproducible.
+    pub seed: Option<u64>,
+    // If set, battles are recorded for the visualizer.
+    pub record_dir: Option<PathBuf>,
+    // If set, the results are also written to this file.
+    pub report: Option<PathBuf>,
+    // If set, the ratings in this file are updated with every battle.
+    pub ratings: Option<PathBuf>,
+    // If set, per-game results and their summary are exported into this directory as CSV and JSON.
+    pub export_dir: Option<PathBuf>,
 }
 
-struct Arena {
-    bot_makers: Vec<BotMaker>,
-    maps: Vec<PathBuf>,
-    games_per_map: usize,
+// Outputs chosen on the command line. They override those of an arena config.
+#[derive(Debug, Default, Clone)]
+pub struct ArenaOutputs {
+    pub record_dir: Option<PathBuf>,
+    pub ratings: Option<PathBuf>,
+    pub export_dir: Option<PathBuf>,
+}
+
+impl ArenaOutputs {
+    fn apply(self, arena: &mut Arena) {
+        if self.record_dir.is_some() {
+            arena.record_dir = self.record_dir;
+        }
+        if self.ratings.is_some() {
+            arena.ratings = self.ratings;
+        }
+        if self.export_dir.is_some() {
+            arena.export_dir = self.export_dir;
+        }
+    }
 }
 
 impl Arena {
-    pub fn run(&self) {
-
-        let maps = self.maps.iter().map(|m| read_map(m)).collect::<Vec<_>>();
-        let total_turns = maps.iter().map(|m| m.rivers.len()).sum::<usize>() * self.games_per_map;
-
-        let listener = Arc::new(Mutex::new(
-            Listener { bar: pbr::ProgressBar::new(total_turns as u64) },
-        ));
-        let settings: Settings = Default::default();
-
-        self.maps.par_iter().for_each(|map_path| {
-            let map = read_map(map_path);
-
-            let results = (0..self.games_per_map)
-                .collect::<Vec<_>>()
-                .par_iter()
-                .flat_map(|_| {
-                    let mut bots = self.bot_makers
-                        .iter()
-                        .map(|b| b.make())
-                        .collect::<Vec<Box<Bot>>>();
-                    let mut rng = rand::thread_rng();
-                    rng.shuffle(&mut bots);
-                    let battle = Battle::new(map.clone(), settings.clone(), bots, Some(listener.clone()));
-                    battle.run().expect("fails")
+    // Plays games_per_map games per settings combination on a map in parallel. Seats are shuffled, and the first
+    // `punters` bots play.
+    pub fn play_map(&self, map_index: usize, map: &Map, listener: Option<Arc<Mutex<Listener>>>) -> Vec<PunterScore> {
+        let record_dir = self.record_dir.clone().or_else(env_record_dir);
+        let games = self.settings.len() * self.games_per_map;
+        (0..games)
+            .collect::<Vec<_>>()
+            .par_iter()
+            .flat_map(|id_in_map| {
+                let (settings_index, game_index) = (id_in_map / self.games_per_map, id_in_map % self.games_per_map);
+                let mut bots = self.bot_makers
+                    .iter()
+                    .map(|b| b.make())
+                    .collect::<Vec<Box<Bot>>>();
+                match self.seed {
+                    Some(seed) => {
+                        let mut rng = StdRng::from_seed(&[seed as usize, map_index, game_index][..]);
+                        rng.shuffle(&mut bots);
+                    }
+                    None => {
+                        let mut rng = rand::thread_rng();
+                        rng.shuffle(&mut bots);
+                    }
+                }
+                if let Some(punters) = self.punters {
+                    bots.truncate(punters);
+                }
+                let id = map_index * games + id_in_map;
+                let settings = self.settings[settings_index].clone();
+                let battle = Battle::new(id, map.clone(), settings, bots, listener.clone(), record_dir.clone());
+                battle.run().expect("fails")
+            })
+            .collect::<Vec<PunterScore>>()
+    }
 
-                })
-                .collect::<Vec<PunterScore>>();
+    // Runs with a progress bar.
+    pub fn run(&self) -> PunterResult<()> {
+        let maps = self.maps.iter().map(|m| read_map(m)).collect::<PunterResult<Vec<_>>>()?;
+        let total_turns = maps.iter().map(|m| m.rivers.len()).sum::<usize>() * self.settings.len() * self.games_per_map;
+        self.run_with_listener(Arc::new(Mutex::new(ProgressListener { bar: pbr::ProgressBar::new(total_turns as u64) })))
+    }
+
+    pub fn run_with_listener(&self, listener: Arc<Mutex<Listener>>) -> PunterResult<()> {
+        let maps = self.maps.iter().map(|m| read_map(m)).collect::<PunterResult<Vec<_>>>()?;
+        let reports = Mutex::new(vec![]);
+        let rows = Mutex::new(vec![]);
+        let rating_log = match self.ratings {
+            Some(ref path) => Some(Mutex::new(RatingLog::new(path)?)),
+            None => None,
+        };
+        self.maps.par_iter().zip(maps.par_iter()).enumerate().for_each(|(map_index, (map_path, map))| {
+            let results = self.play_map(map_index, map, Some(listener.clone()));
+            let map_name = map_path.file_stem().unwrap().to_str().unwrap();
+            rows.lock().unwrap().extend(results.iter().map(|r| GameRow::new(map_name, r)));
+            let mut battles = BTreeMap::new();
             let mut stats = ArenaStats::new();
             for r in results {
+                battles.entry(r.battle).or_insert_with(Vec::new).push((r.bot_name.clone(), r.score));
                 stats.add(r);
             }
-            let stdout = std::io::stdout();
-            let mut handle = stdout.lock();
-            writeln!(
-                &mut handle,
-                "map: {} (cities: {}, rivers: {})",
-                map_path.file_stem().unwrap().to_str().unwrap(),
+            if let Some(ref rating_log) = rating_log {
+                if let Err(e) = rating_log.lock().unwrap().add(map_index, battles) {
+                    warn!("failed to save ratings: {}", e);
+                }
+            }
+            let result = format!(
+                "map: {} (cities: {}, rivers: {})\n{}",
+                map_name,
                 map.sites.len(),
-                map.rivers.len()
-            ).unwrap();
-            writeln!(&mut handle, "{}", stats).unwrap();
+                map.rivers.len(),
+                stats
+            );
+            println!("{}", result);
+            reports.lock().unwrap().push((map_index, result));
         });
+        let mut reports = reports.into_inner().unwrap();
+        reports.sort_by_key(|&(map_index, _)| map_index);
+        let mut rows = rows.into_inner().unwrap();
+        let comparison = format!("all maps\n{}", compare::compare(&rows));
+        println!("{}", comparison);
+        reports.push((maps.len(), comparison));
+        if let Some(rating_log) = rating_log {
+            let rating_log = rating_log.into_inner().unwrap();
+            rating_log.ratings.save(&rating_log.path)?;
+            let leaderboard = format!("ratings: {}\n{}", rating_log.path.display(), rating_log.ratings);
+            println!("{}", leaderboard);
+            reports.push((maps.len() + 1, leaderboard));
+        }
+        if let Some(ref dir) = self.export_dir {
+            export::write(dir, &mut rows)?;
+        }
+        if let Some(ref report) = self.report {
+            let mut f = fs::File::create(report)?;
+            for (_, result) in reports {
+                writeln!(f, "{}", result)?;
+            }
+        }
+        Ok(())
     }
 }
 
-fn builtin_map_path(map_name: &str) -> PathBuf {
+// Rates the battles of a map once it and all maps before it are played, and saves the ratings then, so that a run
+// which fails part way keeps the ratings of the maps played so far. Battles are rated in the order of their ids, so
+// that a seeded run gives the same ratings.
+struct RatingLog {
+    path: PathBuf,
+    ratings: Ratings,
+    next_map: usize,
+    // Battles of maps played ahead of next_map, by map and battle id, as (name, score) by seat.
+    pending: BTreeMap<usize, BTreeMap<usize, Vec<(String, i64)>>>,
+}
+
+impl RatingLog {
+    fn new(path: &Path) -> PunterResult<RatingLog> {
+        Ok(RatingLog {
+            path: path.to_owned(),
+            ratings: Ratings::load(path)?,
+            next_map: 0,
+            pending: BTreeMap::new(),
+        })
+    }
+
+    fn add(&mut self, map_index: usize, battles: BTreeMap<usize, Vec<(String, i64)>>) -> PunterResult<()> {
+        self.pending.insert(map_index, battles);
+        let mut rated = false;
+        while let Some(battles) = self.pending.remove(&self.next_map) {
+            for (_, result) in battles {
+                self.ratings.update(&result);
+            }
+            self.next_map += 1;
+            rated = true;
+        }
+        if rated {
+            self.ratings.save(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn builtin_map_path(map_name: &str) -> PathBuf {
     let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
     path.push("task/maps");
     path.push(map_name);
     path
 }
 
-fn read_map(path: &Path) -> Map {
-    let mut f = fs::File::open(&path).unwrap();
+pub fn read_map(path: &Path) -> PunterResult<Map> {
+    let mut f = fs::File::open(&path)?;
     let mut s = String::new();
-    f.read_to_string(&mut s).unwrap();
-    serde_json::from_str(&s).unwrap()
+    f.read_to_string(&mut s)?;
+    let map: Map = serde_json::from_str(&s)?;
+    validate::check(&map).map_err(|e| PunterError::InvalidMap(format!("{}: {}", path.display(), e)))?;
+    Ok(map)
 }
 
-#[derive(Debug, Serialize)]
-struct VisGraph {
-    map: Map,
-    moves: Vec<Move>,
+// The game after the first `turns` moves, with every punter's claims.
+pub fn replay(map: &Map, moves: &[Move], turns: usize) -> Game {
+    let mut game: Game = SetupSP {
+        punter: 0,
+        punters: moves.iter().map(|m| m.punter() + 1).max().unwrap_or(1),
+        map: map.clone(),
+        settings: None,
+    }.into();
+    for m in moves.iter().take(turns) {
+        game.apply_move(m.clone());
+    }
+    game
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BattleEventKind {
+    // The bot failed, and passed instead.
+    Error,
+    Illegal,
+    Timeout,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BattleEvent {
+    pub turn: usize,
+    pub punter: PunterId,
+    pub kind: BattleEventKind,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TurnRecord {
+    pub millis: u64,
+    // Every punter's score after the move.
+    pub scores: Vec<i64>,
+}
+
+// A recorded battle, which the visualizer replays. Fields other than map and moves are missing in old records.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct VisGraph {
+    pub map: Map,
+    pub moves: Vec<Move>,
+    #[serde(default)]
+    pub punters: Vec<String>, // bot names in seat order
+    #[serde(default)]
+    pub settings: Settings,
+    #[serde(default)]
+    pub futures: Vec<Vec<Future>>, // by punter
+    #[serde(default)]
+    pub turns: Vec<TurnRecord>, // in the order of moves
+    #[serde(default)]
+    pub events: Vec<BattleEvent>,
 }
 
 impl VisGraph {
-    fn new(map: Map, moves: Vec<Move>) -> Self {
-        VisGraph { map, moves }
+    pub fn replay(&self, turns: usize) -> Game {
+        replay(&self.map, &self.moves, turns)
     }
 
-    fn write_vis_graph_json(&self) {
-        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        let local = chrono::Local::now();
-        path.push("visualizer/data");
-        path.push(local.format("%Y-%m-%d-%H-%M-%S-%f.json").to_string());
-
-        let json = serde_json::to_string(self).unwrap();
-        let mut n = std::fs::File::create(&path).unwrap();
+    // Writes the record as <dir>/<time>.json, and points <dir>/latest.json to it.
+    pub fn write<P: AsRef<Path>>(&self, dir: P) -> PunterResult<PathBuf> {
+        let dir = dir.as_ref();
+        fs::create_dir_all(dir)?;
+        let name = chrono::Local::now().format("%Y-%m-%d-%H-%M-%S-%f.json").to_string();
+        let path = dir.join(&name);
         info!(">> Writing graph as {}", path.display());
-        n.write(json.as_bytes()).unwrap();
+        serde_json::to_writer(fs::File::create(&path)?, self)?;
+
+        // Battles finishing at the same time may race for the link, which is fine.
+        let link = dir.join("latest.json");
+        if fs::symlink_metadata(&link).is_ok() {
+            fs::remove_file(&link).ok();
+        }
+        if let Err(e) = link_latest(&name, &path, &link) {
+            warn!("failed to link {}: {}", link.display(), e);
+        }
+        Ok(path)
+    }
+}
+
+// latest.json is a symbolic link to the latest record where links are cheap, and a copy of it elsewhere.
+#[cfg(unix)]
+fn link_latest(name: &str, _path: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(name, link)
+}
+
+#[cfg(not(unix))]
+fn link_latest(_name: &str, path: &Path, link: &Path) -> std::io::Result<()> {
+    fs::copy(path, link).map(|_| ())
+}
+
+// Judges the moves of a battle with a game of its own, which also gives the scores. Futures and the budgets of options
+// and splurges are kept by punter, and only count with their extension on.
+struct Referee {
+    game: Game,
+    settings: Settings,
+    // Valid futures only, by punter.
+    futures: Vec<Vec<Future>>,
+    // Passes not spent on splurges yet.
+    credits: Vec<usize>,
+    options: Vec<usize>,
+}
+
+impl Referee {
+    fn new(map: &Map, settings: &Settings, punters: usize) -> Referee {
+        Referee {
+            game: SetupSP {
+                punter: 0,
+                punters,
+                map: map.clone(),
+                settings: Some(settings.clone()),
+            }.into(),
+            settings: settings.clone(),
+            futures: vec![vec![]; punters],
+            credits: vec![0; punters],
+            options: vec![0; punters],
+        }
+    }
+
+    // Futures from a site which is not a mine, or to a mine, are ignored, and a later future from the same mine
+    // replaces an earlier one.
+    fn bid(&mut self, punter: PunterId, futures: Option<Vec<Future>>) {
+        if self.settings.futures != Some(true) {
+            return;
+        }
+        let mines = self.game.mines().iter().map(|&m| self.game.node_to_site_id(m)).collect::<Vec<_>>();
+        let mut valid: Vec<Future> = vec![];
+        for future in futures.unwrap_or_default() {
+            if !mines.contains(&future.source) || mines.contains(&future.target) {
+                warn!("punter {}: invalid future ignored: {:?}", punter, future);
+                continue;
+            }
+            valid.retain(|f| f.source != future.source);
+            valid.push(future);
+        }
+        self.futures[punter] = valid;
+    }
+
+    // Why `punter` can not play `mov` now, if it can not.
+    fn judge(&self, punter: PunterId, mov: &Move) -> Result<(), String> {
+        if mov.punter() != punter {
+            return Err(format!("a move for punter {}", mov.punter()));
+        }
+        match *mov {
+            Move::Option_ { .. } => {
+                if self.settings.options != Some(true) {
+                    return Err("options are off".to_string());
+                }
+                if self.options[punter] >= self.game.mines().len() {
+                    return Err(format!("no options left after {}", self.options[punter]));
+                }
+            }
+            Move::Splurge { ref splurge } => {
+                if self.settings.splurge != Some(true) {
+                    return Err("splurges are off".to_string());
+                }
+                let rivers = splurge.route.len().saturating_sub(1);
+                if rivers > self.credits[punter] + 1 {
+                    return Err(format!("a splurge of {} rivers after {} passes", rivers, self.credits[punter]));
+                }
+                // Claims the route river by river, as it may use a river twice.
+                let mut game = self.game.clone();
+                for river in splurge.route.windows(2) {
+                    let claim = Move::from(Claim {
+                        punter,
+                        source: river[0],
+                        target: river[1],
+                    });
+                    if !game.is_legal_move(&claim) {
+                        return Err(format!("river {}-{} can not be claimed", river[0], river[1]));
+                    }
+                    game.apply_move(claim);
+                }
+            }
+            _ => {}
+        }
+        if !self.game.is_legal_move(mov) {
+            return Err("the river can not be claimed".to_string());
+        }
+        Ok(())
+    }
+
+    // Applies a move which has been judged legal.
+    fn apply(&mut self, mov: Move) {
+        match mov {
+            Move::Pass { ref pass } => self.credits[pass.punter] += 1,
+            Move::Splurge { ref splurge } => self.credits[splurge.punter] -= splurge.route.len() - 2,
+            Move::Option_ { ref option } => self.options[option.punter] += 1,
+            Move::Claim { .. } => {}
+        }
+        self.game.apply_move(mov);
+    }
+
+    fn score(&self, punter: PunterId) -> i64 {
+        self.game.score(punter) + self.game.futures_score(punter, &self.futures[punter])
+    }
+}
 
-        let mut link = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        link.push("visualizer/latest.json");
+// Builds a VisGraph while a battle runs, from moves already judged by a referee and the scores after them.
+struct Recorder {
+    vis_graph: VisGraph,
+}
 
-        if link.exists() {
-            fs::remove_file(&link).unwrap();
+impl Recorder {
+    fn new(map: &Map, settings: &Settings, punters: Vec<String>, futures: Vec<Vec<Future>>) -> Recorder {
+        Recorder {
+            vis_graph: VisGraph {
+                map: map.clone(),
+                moves: vec![],
+                punters,
+                settings: settings.clone(),
+                futures,
+                turns: vec![],
+                events: vec![],
+            },
         }
-        std::os::unix::fs::symlink(&path, &link).unwrap();
+    }
+
+    fn record(
+        &mut self,
+        punter: PunterId,
+        mov: &Move,
+        elapsed: std::time::Duration,
+        error: Option<String>,
+        illegal: Option<String>,
+        scores: Vec<i64>,
+    ) {
+        let turn = self.vis_graph.moves.len();
+        let millis = millis(elapsed);
+        let mut events = vec![];
+        if let Some(message) = error {
+            events.push((BattleEventKind::Error, message));
+        }
+        if millis > MOVE_TIME_LIMIT_MILLIS {
+            events.push((BattleEventKind::Timeout, format!("{}ms", millis)));
+        }
+        if let Some(message) = illegal {
+            events.push((BattleEventKind::Illegal, message));
+        }
+        for (kind, message) in events {
+            warn!("turn {}: punter {}: {:?}: {}", turn, punter, kind, message);
+            self.vis_graph.events.push(BattleEvent {
+                turn,
+                punter,
+                kind,
+                message,
+            });
+        }
+        self.vis_graph.moves.push(mov.clone());
+        self.vis_graph.turns.push(TurnRecord { millis, scores });
+    }
+}
+
+// Where battles are recorded if no directory is given: visualizer/data, if MY_ICFP2017_RECORD_BATTLE is set.
+fn env_record_dir() -> Option<PathBuf> {
+    if std::env::var("MY_ICFP2017_RECORD_BATTLE").is_ok() {
+        let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        path.push("visualizer/data");
+        Some(path)
+    } else {
+        None
     }
 }
 
 pub fn sample_battle(builtin_map_name: &str) -> ArenaStats {
-    let map = read_map(&builtin_map_path(builtin_map_name));
+    let map = read_map(&builtin_map_path(builtin_map_name)).unwrap();
     let settings: Settings = Default::default();
     let bots: Vec<Box<Bot>> = vec![
         Box::new(bot::InternalBot::new(Strategy::EdgeWeight)),
@@ -362,12 +834,22 @@ pub fn sample_battle(builtin_map_name: &str) -> ArenaStats {
     ];
 
     let mut stats = ArenaStats::new();
-    for r in Battle::new(map, settings, bots, None).run().unwrap() {
+    for r in Battle::new(0, map, settings, bots, None, None).run().unwrap() {
         stats.add(r);
     }
     stats
 }
 
+// A fresh two-player game on a builtin map, as seen by punter 0.
+pub fn sample_game(builtin_map_name: &str) -> Game {
+    SetupSP {
+        punter: 0,
+        punters: 2,
+        map: read_map(&builtin_map_path(builtin_map_name)).unwrap(),
+        settings: None,
+    }.into()
+}
+
 #[test]
 fn regression_test() {
     let stats = sample_battle("lambda.json");
@@ -383,77 +865,209 @@ fn regression_test() {
     assert_eq!(s.score, [89044, 95786]);
 }
 
-pub fn internal_arena_run() -> PunterResult<()> {
-    let maps = [
-        "lambda.json",
-        "Sierpinski-triangle.json",
-        "circle.json",
-        "randomMedium.json",
-        "randomSparse.json",
-    ].iter()
-        .map(|p| builtin_map_path(p))
-        .collect();
+#[test]
+fn settings_matrix_test() {
     let arena = Arena {
-        bot_makers: vec![
-            BotMaker::Internal(Strategy::Stupid),
-            BotMaker::Internal(Strategy::EdgeWeight),
-        ],
-        maps,
-        games_per_map: 8,
+        bot_makers: vec![BotMaker::Internal(Strategy::EdgeWeight), BotMaker::Internal(Strategy::Greedy)],
+        maps: vec![builtin_map_path("sample.json")],
+        games_per_map: 2,
+        punters: None,
+        settings: vec!["none".parse().unwrap(), "all".parse().unwrap()],
+        seed: Some(1),
+        record_dir: None,
+        report: None,
+        ratings: None,
+        export_dir: None,
     };
-    arena.run();
-    Ok(())
+    let map = read_map(&arena.maps[0]).unwrap();
+    let mut stats = ArenaStats::new();
+    for r in arena.play_map(0, &map, None) {
+        stats.add(r);
+    }
+    assert_eq!(stats.stats["Greedy"].score.len(), 4);
+    assert_eq!(stats.by_settings.keys().collect::<Vec<_>>(), vec!["all", "none"]);
+    assert_eq!(stats.by_settings["all"]["EdgeWeight"].point.len(), 2);
+    assert!(stats.to_string().contains("settings: none\n"));
 }
 
-pub fn arena_run<P: AsRef<Path>>(bot_programs: Vec<P>) -> PunterResult<()> {
-    let bot_makers = bot_programs
-        .into_iter()
-        .map(|p| BotMaker::Offline(p.as_ref().to_owned()))
-        .collect();
+#[test]
+fn rating_log_test() {
+    let path = std::env::temp_dir().join("icfp2017-rating-log-test.json");
+    fs::remove_file(&path).ok();
+    let battle = |winner: &str, loser: &str| vec![(winner.to_string(), 2), (loser.to_string(), 1)];
+    let map = |battles: Vec<(usize, Vec<(String, i64)>)>| battles.into_iter().collect::<BTreeMap<_, _>>();
 
-    let maps = [
-        // // "sample.json",
-        // "lambda.json",
-        // "Sierpinski-triangle.json",
-        // "circle.json",
-        // "randomMedium.json",
-        // "randomSparse.json",
-        // "tube.json",
-        "oxford-10000.json",
-        "oxford-center-sparse.json",
-        "oxford.json",
-        "edinburgh-sparse.json",
-        // "boston-sparse.json",
-        "nara-sparse.json",
-        "van-city-sparse.json",
-        "gothenburg-sparse.json",
-        // "edinburgh-10000.json",
-        // "icfp-coauthors-pj.json",
-        // "junction.json",
-    ].iter()
-        .map(|p| builtin_map_path(p))
-        .collect();
+    // Map 1 waits for map 0, and ratings are saved as soon as both are rated.
+    let mut log = RatingLog::new(&path).unwrap();
+    log.add(1, map(vec![(2, battle("b", "a"))])).unwrap();
+    assert!(!path.exists());
+    log.add(0, map(vec![(0, battle("a", "b")), (1, battle("a", "b"))])).unwrap();
+    let saved = Ratings::load(&path).unwrap();
+    fs::remove_file(&path).ok();
 
-    let arena = Arena {
-        bot_makers,
-        maps,
-        games_per_map: 8,
+    let mut expected = Ratings::default();
+    for result in vec![battle("a", "b"), battle("a", "b"), battle("b", "a")] {
+        expected.update(&result);
+    }
+    assert_eq!(saved.bots, expected.bots);
+    assert_eq!(saved.get("a").games, 3);
+}
+
+#[test]
+fn referee_test() {
+    let map = read_map(&builtin_map_path("sample.json")).unwrap();
+    let claim = |punter, source, target| Move::from(Claim { punter, source, target });
+    let option = |punter, source, target| Move::Option_ { option: Claim { punter, source, target } };
+    let splurge = |punter, route: &[SiteId]| Move::Splurge { splurge: Splurge { punter, route: route.to_vec() } };
+    let pass = |punter| Move::Pass { pass: Pass { punter } };
+    let future = |source, target| Future { source, target };
+
+    let mut referee = Referee::new(&map, &"all".parse().unwrap(), 2);
+    // Only 1-2 is kept: 0 is not a mine, 1 is, and 1-2 replaces 1-3.
+    referee.bid(0, Some(vec![future(1, 3), future(0, 2), future(5, 1), future(1, 2)]));
+    assert_eq!(referee.futures[0].iter().map(|f| (f.source, f.target)).collect::<Vec<_>>(), vec![(1, 2)]);
+
+    {
+        let mut play = |punter, mov: Move| {
+            let judgement = referee.judge(punter, &mov);
+            if judgement.is_ok() {
+                referee.apply(mov);
+            }
+            judgement.is_ok()
+        };
+        assert!(!play(0, claim(1, 0, 1)));
+        assert!(play(0, claim(0, 0, 1)));
+        assert!(!play(0, option(0, 0, 1)));
+        assert!(play(1, option(1, 0, 1)));
+        assert!(play(0, claim(0, 1, 2)));
+        assert!(play(1, option(1, 1, 2)));
+        assert!(play(0, claim(0, 7, 6)));
+        // As many options as mines.
+        assert!(!play(1, option(1, 7, 6)));
+        // A splurge of two rivers takes a pass first, and may not use a river twice.
+        assert!(!play(0, splurge(0, &[3, 4, 5])));
+        assert!(play(0, pass(0)));
+        assert!(!play(0, splurge(0, &[5, 3, 5])));
+        assert!(play(0, splurge(0, &[3, 4, 5])));
+        assert!(!play(0, splurge(0, &[2, 3, 5])));
+    }
+    assert_eq!(referee.credits, vec![0, 0]);
+    assert_eq!(referee.score(0), referee.game.score(0) + 1);
+
+    let referee = Referee::new(&map, &Default::default(), 2);
+    assert!(referee.judge(0, &option(0, 0, 1)).unwrap_err().contains("options are off"));
+    assert!(referee.judge(0, &splurge(0, &[0, 1])).unwrap_err().contains("splurges are off"));
+}
+
+#[test]
+fn recorder_test() {
+    use std::time::Duration;
+
+    let map = read_map(&builtin_map_path("sample.json")).unwrap();
+    let names = vec!["a".to_string(), "b".to_string()];
+    let mut referee = Referee::new(&map, &Default::default(), 2);
+    let mut recorder = Recorder::new(&map, &Default::default(), names, vec![vec![], vec![]]);
+    {
+        let mut record = |punter, mov: Move, millis, error: Option<&str>| {
+            let illegal = referee.judge(punter, &mov).err();
+            let mov = if illegal.is_some() { Move::Pass { pass: Pass { punter } } } else { mov };
+            referee.apply(mov.clone());
+            let scores = vec![referee.score(0), referee.score(1)];
+            recorder.record(punter, &mov, Duration::from_millis(millis), error.map(str::to_string), illegal, scores);
+        };
+        let claim = |punter, source, target| Move::from(Claim { punter, source, target });
+        record(0, claim(0, 0, 1), 10, None);
+        record(1, claim(1, 1, 0), 1500, None);
+        record(0, Move::Pass { pass: Pass { punter: 0 } }, 0, Some("crashed"));
+    }
+
+    let vis_graph = recorder.vis_graph;
+    assert_eq!(vis_graph.moves.len(), 3);
+    assert!(match vis_graph.moves[1] {
+        Move::Pass { .. } => true,
+        _ => false,
+    });
+    assert_eq!(vis_graph.turns[0].scores, vec![1, 0]);
+    assert_eq!(vis_graph.turns[2].scores, vec![1, 0]);
+    assert_eq!(vis_graph.turns[1].millis, 1500);
+    assert_eq!(
+        vis_graph.events.iter().map(|e| (e.turn, e.punter, e.kind)).collect::<Vec<_>>(),
+        vec![
+            (1, 1, BattleEventKind::Timeout),
+            (1, 1, BattleEventKind::Illegal),
+            (2, 0, BattleEventKind::Error),
+        ]
+    );
+
+    // Old records have only a map and moves.
+    let old: VisGraph = serde_json::from_str(r#"{"map": {"sites": [], "rivers": [], "mines": []}, "moves": []}"#).unwrap();
+    assert!(old.punters.is_empty() && old.turns.is_empty());
+}
+
+pub fn builtin_config_path(config_name: &str) -> PathBuf {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("arena");
+    path.push(config_name);
+    path
+}
+
+fn config_arena_run(config: ArenaConfig, outputs: ArenaOutputs) -> PunterResult<()> {
+    let mut arena = config.to_arena()?;
+    outputs.apply(&mut arena);
+    arena.run()
+}
+
+// Internal strategies as set up in arena/internal.json. If params are given, EdgeWeight with the params joins the arena.
+pub fn internal_arena_run(params: Option<EdgeWeightParams>, outputs: ArenaOutputs) -> PunterResult<()> {
+    let mut config = ArenaConfig::from_file(builtin_config_path("internal.json"))?;
+    if let Some(params) = params {
+        config.bots.push(BotConfig {
+            name: Some("EdgeWeight(params)".to_string()),
+            params: Some(params),
+            ..BotConfig::internal("EdgeWeight")
+        });
+    }
+    config_arena_run(config, outputs)
+}
+
+// Programs join the bots of `config`, or of arena/offline.json by default.
+pub fn arena_run<P: AsRef<Path>>(bot_programs: Vec<P>, config: Option<&Path>, outputs: ArenaOutputs) -> PunterResult<()> {
+    let mut config = match config {
+        Some(path) => ArenaConfig::from_file(path)?,
+        None => ArenaConfig::from_file(builtin_config_path("offline.json"))?,
     };
-    arena.run();
-    Ok(())
+    config.bots.extend(bot_programs.into_iter().map(BotConfig::program));
+    config_arena_run(config, outputs)
 }
 
-pub fn single_match<P: AsRef<Path>>(bot_programs: Vec<P>, map_path: P, games: usize) -> PunterResult<()> {
+// Reports to `listener` if given, instead of a progress bar.
+pub fn single_match<P: AsRef<Path>>(
+    bot_programs: Vec<P>,
+    map_path: P,
+    games: usize,
+    listener: Option<Arc<Mutex<Listener>>>,
+    outputs: ArenaOutputs,
+) -> PunterResult<()> {
     let bot_makers = bot_programs
         .into_iter()
         .map(|p| BotMaker::Offline(p.as_ref().to_owned()))
         .collect();
 
-    let arena = Arena {
+    let mut arena = Arena {
         bot_makers,
         maps: vec![map_path.as_ref().to_owned()],
         games_per_map: games,
+        punters: None,
+        settings: vec![Default::default()],
+        seed: None,
+        record_dir: None,
+        report: None,
+        ratings: None,
+        export_dir: None,
     };
-    arena.run();
-    Ok(())
+    outputs.apply(&mut arena);
+    match listener {
+        Some(listener) => arena.run_with_listener(listener),
+        None => arena.run(),
+    }
 }
@@ -0,0 +1,253 @@
+use punter::arena::Arena;
+use punter::bot::BotMaker;
+use punter::params::EdgeWeightParams;
+use punter::prelude::*;
+use punter::protocol::*;
+use serde_json;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// A tournament setup, e.g.
+//   {"bots": [{"strategy": "EdgeWeight"}, {"strategy": "EdgeWeight", "name": "tuned", "params": {"contention_penalty": 0.5}},
+//             {"program": "../punter"}],
+//    "maps": ["../task/maps/*-sparse.json"], "games_per_map": 8, "punters": 2, "settings": ["none", "futures", "all"],
+//    "seed": 1}
+// Relative paths are relative to the config file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArenaConfig {
+    pub bots: Vec<BotConfig>,
+    // Paths, or globs with * and ? in the file name.
+    pub maps: Vec<String>,
+    #[serde(default = "default_games_per_map")]
+    pub games_per_map: usize,
+    // Bots seated in each game, drawn from bots. All of them by default.
+    pub punters: Option<usize>,
+    // Settings combinations, each of which every game is played with. Only the default settings if empty.
+    #[serde(default)]
+    pub settings: Vec<SettingsConfig>,
+    pub seed: Option<u64>,
+    // Where battles are recorded for the visualizer.
+    pub record_dir: Option<String>,
+    // A file the results are written to, in addition to stdout.
+    pub report: Option<String>,
+    // A ratings file, updated with every battle and kept across runs.
+    pub ratings: Option<String>,
+    // Where per-game results are exported as CSV and JSON.
+    pub export_dir: Option<String>,
+}
+
+// Either an internal strategy, optionally named and with EdgeWeight parameters, or an external program, optionally
+// named. Bots are rated by name, which is the path of a program by default.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BotConfig {
+    pub strategy: Option<String>,
+    pub name: Option<String>,
+    pub params: Option<EdgeWeightParams>,
+    pub program: Option<String>,
+}
+
+// Either a name such as "none", "futures+options" or "all" (see Settings's FromStr), or settings as in the protocol.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum SettingsConfig {
+    Name(String),
+    Settings(Settings),
+}
+
+impl SettingsConfig {
+    fn settings(&self) -> PunterResult<Settings> {
+        match *self {
+            SettingsConfig::Name(ref name) => name.parse(),
+            SettingsConfig::Settings(ref settings) => Ok(settings.clone()),
+        }
+    }
+}
+
+fn default_games_per_map() -> usize {
+    8
+}
+
+// Whether `name` matches `pattern`, where * matches any string and ? any character.
+fn wildcard_match(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some(&'*') => (0..name.len() + 1).any(|i| wildcard_match(&pattern[1..], &name[i..])),
+        Some(&c) => !name.is_empty() && (c == '?' || c == name[0]) && wildcard_match(&pattern[1..], &name[1..]),
+    }
+}
+
+// Files matching a glob, sorted. Only the file name may contain wildcards.
+pub fn glob(pattern: &Path) -> PunterResult<Vec<PathBuf>> {
+    let file_pattern = match pattern.file_name().and_then(|name| name.to_str()) {
+        Some(name) if name.contains('*') || name.contains('?') => name.chars().collect::<Vec<_>>(),
+        _ => return Ok(vec![pattern.to_owned()]),
+    };
+    let dir = pattern.parent().unwrap_or_else(|| Path::new("."));
+    let mut paths = vec![];
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let matched = match path.file_name().and_then(|name| name.to_str()) {
+            Some(name) => wildcard_match(&file_pattern, &name.chars().collect::<Vec<_>>()),
+            None => false,
+        };
+        if matched {
+            paths.push(path);
+        }
+    }
+    if paths.is_empty() {
+        return Err(PunterError::InvalidArgument(format!("no maps match {}", pattern.display())));
+    }
+    paths.sort();
+    Ok(paths)
+}
+
+impl BotConfig {
+    pub fn internal(strategy: &str) -> BotConfig {
+        BotConfig {
+            strategy: Some(strategy.to_string()),
+            name: None,
+            params: None,
+            program: None,
+        }
+    }
+
+    pub fn program<P: AsRef<Path>>(program: P) -> BotConfig {
+        BotConfig {
+            strategy: None,
+            name: None,
+            params: None,
+            program: Some(program.as_ref().to_string_lossy().into_owned()),
+        }
+    }
+
+    fn bot_maker(&self) -> PunterResult<BotMaker> {
+        match (self.strategy.as_ref(), self.program.as_ref()) {
+            (Some(strategy), None) => {
+                let strategy = strategy.parse()?;
+                match (self.name.as_ref(), self.params) {
+                    (None, None) => Ok(BotMaker::Internal(strategy)),
+                    (name, params) => {
+                        Ok(BotMaker::Parameterised {
+                            name: name.cloned().unwrap_or_else(|| format!("{:?}", strategy)),
+                            strategy,
+                            params: params.unwrap_or_default(),
+                        })
+                    }
+                }
+            }
+            (None, Some(program)) => {
+                if self.params.is_some() {
+                    return Err(PunterError::InvalidArgument(format!("{}: programs take no params", program)));
+                }
+                match self.name {
+                    Some(ref name) => {
+                        Ok(BotMaker::NamedOffline {
+                            name: name.clone(),
+                            program: PathBuf::from(program),
+                        })
+                    }
+                    None => Ok(BotMaker::Offline(PathBuf::from(program))),
+                }
+            }
+            _ => Err(PunterError::InvalidArgument("a bot needs either a strategy or a program".to_string())),
+        }
+    }
+}
+
+impl ArenaConfig {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> PunterResult<ArenaConfig> {
+        let mut config: ArenaConfig = serde_json::from_reader(fs::File::open(path.as_ref())?)?;
+        // Makes relative paths relative to the config file.
+        let base = path.as_ref().parent().unwrap_or_else(|| Path::new("."));
+        let resolve = |p: &String| base.join(p).to_string_lossy().into_owned();
+        config.maps = config.maps.iter().map(&resolve).collect();
+        config.record_dir = config.record_dir.as_ref().map(&resolve);
+        config.report = config.report.as_ref().map(&resolve);
+        config.ratings = config.ratings.as_ref().map(&resolve);
+        config.export_dir = config.export_dir.as_ref().map(&resolve);
+        for bot in config.bots.iter_mut() {
+            bot.program = bot.program.as_ref().map(&resolve);
+        }
+        Ok(config)
+    }
+
+    pub fn to_arena(&self) -> PunterResult<Arena> {
+        let bot_makers = self.bots.iter().map(|b| b.bot_maker()).collect::<PunterResult<Vec<_>>>()?;
+        if bot_makers.is_empty() {
+            return Err(PunterError::InvalidArgument("no bots to play".to_string()));
+        }
+        if let Some(punters) = self.punters {
+            if punters == 0 || punters > bot_makers.len() {
+                return Err(PunterError::InvalidArgument(
+                    format!("punters must be between 1 and the number of bots ({})", bot_makers.len()),
+                ));
+            }
+        }
+        let mut maps = vec![];
+        for pattern in self.maps.iter() {
+            maps.extend(glob(Path::new(pattern))?);
+        }
+        let mut settings = self.settings.iter().map(|s| s.settings()).collect::<PunterResult<Vec<_>>>()?;
+        if settings.is_empty() {
+            settings.push(Settings::default());
+        }
+        Ok(Arena {
+            bot_makers,
+            maps,
+            games_per_map: self.games_per_map,
+            punters: self.punters,
+            settings,
+            seed: self.seed,
+            record_dir: self.record_dir.as_ref().map(PathBuf::from),
+            report: self.report.as_ref().map(PathBuf::from),
+            ratings: self.ratings.as_ref().map(PathBuf::from),
+            export_dir: self.export_dir.as_ref().map(PathBuf::from),
+        })
+    }
+}
+
+#[test]
+fn arena_config_test() {
+    use punter::arena;
+
+    let config: ArenaConfig = serde_json::from_str(
+        r#"{"bots": [{"strategy": "Greedy"}, {"strategy": "EdgeWeight", "params": {"contention_penalty": 0.5}},
+                     {"program": "punter", "name": "ours"}],
+            "maps": ["task/maps/*-sparse.json", "task/maps/lambda.json"], "punters": 2,
+            "settings": ["none", "futures+options", {"splurge": true}]}"#,
+    ).unwrap();
+    assert_eq!(config.games_per_map, 8);
+    let maps = config.maps.iter().map(|m| format!("{}/{}", env!("CARGO_MANIFEST_DIR"), m)).collect();
+    let arena = ArenaConfig { maps, ..config }.to_arena().unwrap();
+    assert_eq!(arena.bot_makers.len(), 3);
+    assert_eq!(arena.bot_makers[2].make().name(), "ours");
+    assert_eq!(BotConfig::program("bin/punter").bot_maker().unwrap().make().name(), "bin/punter");
+    assert_eq!(arena.maps.last(), Some(&arena::builtin_map_path("lambda.json")));
+    assert!(arena.maps.len() > 2);
+    assert!(arena.maps.iter().rev().skip(1).all(|m| m.to_str().unwrap().ends_with("-sparse.json")));
+    assert_eq!(
+        arena.settings.iter().map(|s| s.to_string()).collect::<Vec<_>>(),
+        vec!["none", "futures+options", "splurge"]
+    );
+    assert_eq!("all".parse::<Settings>().unwrap().to_string(), "all");
+
+    let bad: ArenaConfig = serde_json::from_str(r#"{"bots": [{"strategy": "Greedy"}], "maps": [], "punters": 2}"#).unwrap();
+    assert!(bad.to_arena().is_err());
+    let bad: ArenaConfig = serde_json::from_str(r#"{"bots": [{"strategy": "Greedy"}], "maps": [], "settings": ["handicap"]}"#)
+        .unwrap();
+    assert!(bad.to_arena().is_err());
+    let mut program = BotConfig::program("punter");
+    program.params = Some(Default::default());
+    assert!(program.bot_maker().is_err());
+    let mut empty: ArenaConfig = serde_json::from_str(r#"{"bots": [], "maps": []}"#).unwrap();
+    match empty.to_arena() {
+        Err(PunterError::InvalidArgument(message)) => assert_eq!(message, "no bots to play"),
+        _ => panic!("a config without bots was accepted"),
+    }
+    empty.bots.push(BotConfig::program("punter"));
+    assert!(empty.to_arena().is_ok());
+    assert!(serde_json::from_str::<ArenaConfig>(r#"{"bots": [], "maps": [], "games": 1}"#).is_err());
+    assert!(wildcard_match(&"a*b?".chars().collect::<Vec<_>>(), &"axxbc".chars().collect::<Vec<_>>()));
+}
@@ -1,21 +1,40 @@
 use punter::game::{Game, Strategy};
 use punter::io::ChildIO;
+use punter::params::EdgeWeightParams;
 use punter::prelude::*;
 use punter::protocol::*;
 use serde_json;
 use std;
 use std::path::PathBuf;
+use std::time::Instant;
 
 pub enum BotMaker {
     Internal(Strategy),
+    Parameterised {
+        name: String,
+        strategy: Strategy,
+        params: EdgeWeightParams,
+    },
     Offline(PathBuf),
+    NamedOffline {
+        name: String,
+        program: PathBuf,
+    },
 }
 
 impl BotMaker {
     pub fn make(&self) -> Box<Bot> {
         match *self {
             BotMaker::Internal(ref strategy) => Box::new(InternalBot::new(*strategy)),
+            BotMaker::Parameterised {
+                ref name,
+                strategy,
+                params,
+            } => Box::new(InternalBot::with_params(name.clone(), strategy, params)),
             BotMaker::Offline(ref program) => Box::new(OfflineBot::new(program.clone())),
+            BotMaker::NamedOffline { ref name, ref program } => {
+                Box::new(OfflineBot::with_name(name.clone(), program.clone()))
+            }
         }
     }
 }
@@ -29,14 +48,22 @@ pub trait Bot {
 
 #[derive(Debug)]
 pub struct InternalBot {
+    name: String,
     strategy: Strategy,
+    params: EdgeWeightParams,
     game: Option<Game>,
 }
 
 impl InternalBot {
     pub fn new(strategy: Strategy) -> Self {
+        Self::with_params(format!("{:?}", strategy), strategy, Default::default())
+    }
+
+    pub fn with_params(name: String, strategy: Strategy, params: EdgeWeightParams) -> Self {
         Self {
+            name,
             strategy,
+            params,
             game: None,
         }
     }
@@ -44,10 +71,12 @@ impl InternalBot {
 
 impl Bot for InternalBot {
     fn name(&self) -> String {
-        format!("{:?}", self.strategy)
+        self.name.clone()
     }
     fn setup(&mut self, setup: SetupSP) -> PunterResult<OfflineSetupPS> {
-        self.game = Some(setup.into());
+        let mut game: Game = setup.into();
+        game.params = self.params;
+        self.game = Some(game);
         Ok(OfflineSetupPS {
             ready: self.game.as_ref().unwrap().me,
             futures: None,
@@ -56,9 +85,11 @@ impl Bot for InternalBot {
         })
     }
     fn play(&mut self, gameplay: OfflineGamePlaySP) -> PunterResult<OfflineGamePlayPS> {
+        let start = Instant::now();
         let game: &mut Game = self.game.as_mut().unwrap();
         game.apply_moves_excluding_me(gameplay.moves.moves);
-        let mov = game.play(self.strategy);
+        let budget = game.time_control.budget(start);
+        let mov = game.play(self.strategy, &budget);
         game.apply_move(mov.clone());
         Ok(mov.into_offline_game_play_ps(Default::default()))
     }
@@ -69,12 +100,22 @@ impl Bot for InternalBot {
 
 #[derive(Debug)]
 pub struct OfflineBot {
+    name: String,
     program: std::path::PathBuf,
 }
 
 impl OfflineBot {
+    // Named after its path.
     pub fn new<P: AsRef<std::path::Path>>(program: P) -> Self {
-        OfflineBot { program: program.as_ref().to_owned() }
+        let name = program.as_ref().to_string_lossy().into_owned();
+        Self::with_name(name, program)
+    }
+
+    pub fn with_name<P: AsRef<std::path::Path>>(name: String, program: P) -> Self {
+        OfflineBot {
+            name,
+            program: program.as_ref().to_owned(),
+        }
     }
 
     fn handsheke(&self, io: &mut ChildIO) -> PunterResult<()> {
@@ -90,7 +131,7 @@ impl OfflineBot {
 
 impl Bot for OfflineBot {
     fn name(&self) -> String {
-        self.program.as_os_str().to_str().unwrap().to_string()
+        self.name.clone()
     }
     fn setup(&mut self, setup: SetupSP) -> PunterResult<OfflineSetupPS> {
         let mut io = ChildIO::new(&self.program);
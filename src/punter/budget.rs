@@ -0,0 +1,141 @@
+use punter::prelude::*;
+use punter::protocol::*;
+use std::cmp;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+// The protocol gives a punter one second per move.
+pub const MOVE_TIME_LIMIT_MILLIS: u64 = 1000;
+const DEFAULT_MARGIN_MILLIS: u64 = 150;
+const MAX_MARGIN_MILLIS: u64 = 600;
+
+pub fn millis(d: Duration) -> u64 {
+    d.as_secs() * 1000 + (d.subsec_nanos() / 1_000_000) as u64
+}
+
+// Time a strategy may spend on a move. Iterative strategies should poll `is_over()` and return their best
+// move so far once it returns true.
+#[derive(Debug, Clone)]
+pub struct TimeBudget {
+    deadline: Instant,
+    cancelled: Arc<AtomicBool>,
+}
+
+impl TimeBudget {
+    pub fn new(duration: Duration) -> TimeBudget {
+        TimeBudget {
+            deadline: Instant::now() + duration,
+            cancelled: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    pub fn deadline(&self) -> Instant {
+        self.deadline
+    }
+
+    pub fn remaining(&self) -> Duration {
+        let now = Instant::now();
+        if now >= self.deadline {
+            Duration::from_millis(0)
+        } else {
+            self.deadline - now
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed) || Instant::now() >= self.deadline
+    }
+
+    // Cancels every clone of this budget.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+// Per-game time bookkeeping. This is a part of the game state so that it survives offline mode, where
+// each move is computed by a new process.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TimeControl {
+    pub margin_millis: u64,
+    // Estimated time spent after the strategy returns, e.g. encoding the state and writing the message.
+    pub overhead_millis: u64,
+    pub timeouts: usize,
+    sent_claim: bool,
+}
+
+impl Default for TimeControl {
+    fn default() -> Self {
+        TimeControl {
+            margin_millis: DEFAULT_MARGIN_MILLIS,
+            overhead_millis: 0,
+            timeouts: 0,
+            sent_claim: false,
+        }
+    }
+}
+
+impl TimeControl {
+    // The budget for a move whose message was received at `start`.
+    pub fn budget(&self, start: Instant) -> TimeBudget {
+        let limit = MOVE_TIME_LIMIT_MILLIS;
+        let reserved = millis(start.elapsed()) + self.margin_millis + self.overhead_millis;
+        let budget = if reserved < limit { limit - reserved } else { 0 };
+        debug!("time budget: {}ms (reserved: {}ms)", budget, reserved);
+        TimeBudget::new(Duration::from_millis(budget))
+    }
+
+    pub fn record_overhead(&mut self, overhead: Duration) {
+        self.overhead_millis = millis(overhead);
+    }
+
+    pub fn on_timeout(&mut self) {
+        self.timeouts += 1;
+        self.sent_claim = false;
+        self.margin_millis = cmp::min(self.margin_millis * 2, MAX_MARGIN_MILLIS);
+        warn!("timed out. margin is now {}ms", self.margin_millis);
+    }
+
+    pub fn on_move_sent(&mut self, mov: &Move) {
+        self.sent_claim = match *mov {
+            Move::Pass { .. } => false,
+            _ => true,
+        };
+    }
+
+    // The server replaces a move which was too late with a pass, which is the only notice we get in
+    // offline mode.
+    pub fn check_my_last_move(&mut self, me: PunterId, moves: &[Move]) {
+        if !self.sent_claim {
+            return;
+        }
+        self.sent_claim = false;
+        if moves.iter().any(|m| match *m {
+            Move::Pass { ref pass } => pass.punter == me,
+            _ => false,
+        })
+        {
+            self.on_timeout();
+        }
+    }
+}
+
+#[test]
+fn lost_claim_test() {
+    let mut time_control = TimeControl::default();
+    let claim = Move::Claim {
+        claim: Claim {
+            punter: 0,
+            source: 0,
+            target: 1,
+        },
+    };
+    time_control.on_move_sent(&claim);
+    time_control.check_my_last_move(0, &[claim.clone()]);
+    assert_eq!(time_control.timeouts, 0);
+
+    time_control.on_move_sent(&claim);
+    time_control.check_my_last_move(0, &[Move::Pass { pass: Pass { punter: 0 } }]);
+    assert_eq!(time_control.timeouts, 1);
+    assert_eq!(time_control.margin_millis, 2 * DEFAULT_MARGIN_MILLIS);
+}
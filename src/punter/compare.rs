@@ -0,0 +1,308 @@
+use punter::export::GameRow;
+use punter::prelude::*;
+use serde_json;
+use std;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// Two-sided 5% significance, and 80% power when estimating the games needed.
+const Z_ALPHA: f64 = 1.959964;
+const Z_POWER: f64 = 0.841621;
+const SIGNIFICANCE: f64 = 0.05;
+
+// The 97.5% quantile of Student's t distribution: exact values for few degrees of freedom, and the Cornish-Fisher
+// expansion around the normal quantile otherwise, which is within 0.01 of the exact values from there on.
+fn t_quantile(df: usize) -> f64 {
+    const SMALL: [f64; 5] = [12.706, 4.303, 3.182, 2.776, 2.571];
+    if df == 0 {
+        return std::f64::INFINITY;
+    }
+    if df <= SMALL.len() {
+        return SMALL[df - 1];
+    }
+    let (z, n) = (Z_ALPHA, df as f64);
+    z + (z.powi(3) + z) / (4.0 * n) + (5.0 * z.powi(5) + 16.0 * z.powi(3) + 3.0 * z) / (96.0 * n * n) +
+        (3.0 * z.powi(7) + 19.0 * z.powi(5) + 17.0 * z.powi(3) - 15.0 * z) / (384.0 * n * n * n)
+}
+
+// A mean with the half width of its 95% confidence interval, which is infinite for fewer than two samples.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Estimate {
+    pub mean: f64,
+    pub error: f64,
+}
+
+impl Estimate {
+    pub fn of(xs: &[f64]) -> Estimate {
+        let n = xs.len() as f64;
+        let mean = xs.iter().sum::<f64>() / n;
+        if xs.len() < 2 {
+            return Estimate {
+                mean,
+                error: std::f64::INFINITY,
+            };
+        }
+        let variance = xs.iter().map(|x| (x - mean) * (x - mean)).sum::<f64>() / (n - 1.0);
+        Estimate {
+            mean,
+            error: t_quantile(xs.len() - 1) * (variance / n).sqrt(),
+        }
+    }
+}
+
+// The 95% Wilson score interval of a proportion.
+fn wilson(successes: f64, n: f64) -> (f64, f64) {
+    if n == 0.0 {
+        return (0.0, 1.0);
+    }
+    let (p, z2) = (successes / n, Z_ALPHA * Z_ALPHA);
+    let center = (p + z2 / (2.0 * n)) / (1.0 + z2 / n);
+    let half = Z_ALPHA * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / (1.0 + z2 / n);
+    ((center - half).max(0.0), (center + half).min(1.0))
+}
+
+// The two-sided p-value of the sign test: how likely a split at least this uneven is between equal bots.
+fn sign_test(wins: usize, losses: usize) -> f64 {
+    let n = wins + losses;
+    let k = std::cmp::min(wins, losses);
+    // Sums C(n, i) / 2^n for i <= k in log space, as C(n, i) overflows for long runs.
+    let mut log_c = 0.0;
+    let mut p = 0.0;
+    for i in 0..k + 1 {
+        if i > 0 {
+            log_c += ((n - i + 1) as f64).ln() - (i as f64).ln();
+        }
+        p += (log_c - n as f64 * 2f64.ln()).exp();
+    }
+    (2.0 * p).min(1.0)
+}
+
+#[derive(Debug, Clone)]
+pub struct BotComparison {
+    pub bot: String,
+    pub games: usize,
+    pub score: Estimate,
+    // 1 is the best.
+    pub rank: Estimate,
+}
+
+// Bot a against bot b on the games both played. A bot seated more than once in a game counts with its mean score.
+#[derive(Debug, Clone)]
+pub struct PairComparison {
+    pub a: String,
+    pub b: String,
+    pub games: usize,
+    pub wins: usize,
+    pub losses: usize,
+    // The rate of a's wins, counting ties as halves, and its 95% interval.
+    pub win_rate: f64,
+    pub win_rate_interval: (f64, f64),
+    // a's score minus b's score.
+    pub score_difference: Estimate,
+    // The paired sign test on wins and losses.
+    pub p_value: f64,
+}
+
+impl PairComparison {
+    pub fn is_significant(&self) -> bool {
+        self.p_value < SIGNIFICANCE
+    }
+
+    // Games in common the sign test would need to tell the bots apart with 80% power, if the observed win and
+    // tie rates were the true ones. None if they look even.
+    pub fn games_needed(&self) -> Option<usize> {
+        let decided = self.wins + self.losses;
+        if decided == 0 || self.wins == self.losses {
+            return None;
+        }
+        let w = self.wins as f64 / decided as f64;
+        let n = ((Z_ALPHA * 0.5 + Z_POWER * (w * (1.0 - w)).sqrt()) / (w - 0.5)).powi(2);
+        Some((n * self.games as f64 / decided as f64).ceil() as usize)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    // Best first, by mean rank and then by mean score.
+    pub bots: Vec<BotComparison>,
+    pub pairs: Vec<PairComparison>,
+}
+
+pub fn compare(rows: &[GameRow]) -> Comparison {
+    let mut seats: BTreeMap<usize, usize> = BTreeMap::new();
+    for row in rows {
+        *seats.entry(row.game).or_insert(0) += 1;
+    }
+    let mut by_bot: BTreeMap<&str, Vec<&GameRow>> = BTreeMap::new();
+    // game -> bot -> scores
+    let mut by_game: BTreeMap<usize, BTreeMap<&str, Vec<i64>>> = BTreeMap::new();
+    for row in rows {
+        by_bot.entry(&row.bot).or_insert_with(Vec::new).push(row);
+        by_game.entry(row.game).or_insert_with(BTreeMap::new).entry(&row.bot).or_insert_with(Vec::new).push(row.score);
+    }
+
+    let mut bots = by_bot
+        .iter()
+        .map(|(bot, rows)| {
+            BotComparison {
+                bot: bot.to_string(),
+                games: rows.len(),
+                score: Estimate::of(&rows.iter().map(|r| r.score as f64).collect::<Vec<_>>()),
+                rank: Estimate::of(&rows.iter().map(|r| (seats[&r.game] + 1 - r.points) as f64).collect::<Vec<_>>()),
+            }
+        })
+        .collect::<Vec<_>>();
+    bots.sort_by(|a, b| {
+        (a.rank.mean, -a.score.mean).partial_cmp(&(b.rank.mean, -b.score.mean)).unwrap()
+    });
+
+    let mean = |scores: &Vec<i64>| scores.iter().sum::<i64>() as f64 / scores.len() as f64;
+    let mut pairs = vec![];
+    for (i, a) in bots.iter().enumerate() {
+        for b in bots.iter().skip(i + 1) {
+            let differences = by_game
+                .values()
+                .filter_map(|scores| match (scores.get(a.bot.as_str()), scores.get(b.bot.as_str())) {
+                    (Some(sa), Some(sb)) => Some(mean(sa) - mean(sb)),
+                    _ => None,
+                })
+                .collect::<Vec<f64>>();
+            if differences.is_empty() {
+                continue;
+            }
+            let wins = differences.iter().filter(|&&d| d > 0.0).count();
+            let losses = differences.iter().filter(|&&d| d < 0.0).count();
+            let games = differences.len();
+            let won = wins as f64 + (games - wins - losses) as f64 / 2.0;
+            pairs.push(PairComparison {
+                a: a.bot.clone(),
+                b: b.bot.clone(),
+                games,
+                wins,
+                losses,
+                win_rate: won / games as f64,
+                win_rate_interval: wilson(won, games as f64),
+                score_difference: Estimate::of(&differences),
+                p_value: sign_test(wins, losses),
+            });
+        }
+    }
+    Comparison { bots, pairs }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "bots (with 95% confidence intervals):")?;
+        for b in self.bots.iter() {
+            writeln!(
+                f,
+                "{:>32}, games: {:4}, score: {:10.1} +/- {:8.1}, rank: {:5.2} +/- {:4.2}",
+                b.bot,
+                b.games,
+                b.score.mean,
+                b.score.error,
+                b.rank.mean,
+                b.rank.error
+            )?;
+        }
+        writeln!(f, "pairs (on the games both played):")?;
+        for p in self.pairs.iter() {
+            writeln!(
+                f,
+                "{:>32} vs {}, games: {}, win rate: {:.2} ({:.2} - {:.2}), score difference: {:.1} +/- {:.1}, p: {:.4}",
+                p.a,
+                p.b,
+                p.games,
+                p.win_rate,
+                p.win_rate_interval.0,
+                p.win_rate_interval.1,
+                p.score_difference.mean,
+                p.score_difference.error,
+                p.p_value
+            )?;
+        }
+        // The leader against the runner-up.
+        let leaders = match (self.bots.get(0), self.bots.get(1)) {
+            (Some(a), Some(b)) => self.pairs.iter().find(|p| p.a == a.bot && p.b == b.bot),
+            _ => None,
+        };
+        match leaders {
+            None => writeln!(f, "verdict: nothing to compare"),
+            Some(p) if p.is_significant() => {
+                if p.wins > p.losses {
+                    writeln!(f, "verdict: {} beats {} (p: {:.4})", p.a, p.b, p.p_value)
+                } else {
+                    writeln!(f, "verdict: {} beats {} (p: {:.4})", p.b, p.a, p.p_value)
+                }
+            }
+            Some(p) => {
+                match p.games_needed() {
+                    Some(n) => {
+                        writeln!(
+                            f,
+                            "verdict: not enough games to tell {} from {} (p: {:.4}); about {} games in common are needed",
+                            p.a,
+                            p.b,
+                            p.p_value,
+                            n
+                        )
+                    }
+                    None => writeln!(f, "verdict: {} and {} look even (p: {:.4})", p.a, p.b, p.p_value),
+                }
+            }
+        }
+    }
+}
+
+// Compares the bots of games.json, as exported by an arena run.
+pub fn compare_run<P: AsRef<Path>>(games: P) -> PunterResult<Comparison> {
+    let rows: Vec<GameRow> = serde_json::from_reader(fs::File::open(games.as_ref())?)?;
+    Ok(compare(&rows))
+}
+
+#[test]
+fn compare_test() {
+    assert!((t_quantile(10) - 2.228).abs() < 0.01);
+    assert!((t_quantile(30) - 2.042).abs() < 0.01);
+    assert_eq!(sign_test(5, 5), 1.0);
+    assert!((sign_test(9, 1) - 0.02148).abs() < 1e-4);
+    assert!(sign_test(10000, 9000) < 1e-10);
+
+    let row = |game, bot: &str, score, points| GameRow {
+        map: "m".to_string(),
+        settings: "none".to_string(),
+        game,
+        seat: 0,
+        bot: bot.to_string(),
+        score,
+        points,
+        moves: 1,
+        millis: 0,
+        errors: 0,
+        timeouts: 0,
+    };
+    let mut rows = vec![];
+    for game in 0..10 {
+        // a beats b in all games but one, and c is always last.
+        let (a, b) = if game == 0 { (5, 6) } else { (10 + game as i64, 5) };
+        rows.push(row(game, "a", a, if a > b { 3 } else { 2 }));
+        rows.push(row(game, "b", b, if a > b { 2 } else { 3 }));
+        rows.push(row(game, "c", 0, 1));
+    }
+    let comparison = compare(&rows);
+    assert_eq!(comparison.bots.iter().map(|b| b.bot.as_str()).collect::<Vec<_>>(), vec!["a", "b", "c"]);
+    assert!((comparison.bots[0].rank.mean - 1.1).abs() < 1e-9);
+    assert_eq!(comparison.bots[2].rank.error, 0.0);
+    let ab = &comparison.pairs[0];
+    assert_eq!((ab.games, ab.wins, ab.losses), (10, 9, 1));
+    assert!(ab.win_rate_interval.0 < 0.9 && 0.9 < ab.win_rate_interval.1);
+    assert!(ab.is_significant());
+    assert!(comparison.to_string().contains("verdict: a beats b (p: 0.0215)"));
+
+    // Games 1 and 2.
+    let close = compare(&rows[3..9]);
+    assert!(!close.pairs[0].is_significant());
+    assert!(close.to_string().contains("verdict: not enough games to tell a from b"));
+}
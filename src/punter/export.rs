@@ -0,0 +1,173 @@
+use punter::arena::PunterScore;
+use punter::budget::millis;
+use punter::prelude::*;
+use serde_json;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::prelude::*;
+use std::path::Path;
+
+// One seat of one game.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GameRow {
+    pub map: String,
+    pub settings: String,
+    pub game: usize,
+    pub seat: PunterId,
+    pub bot: String,
+    pub score: i64,
+    pub points: usize,
+    pub moves: u64,
+    pub millis: u64,
+    pub errors: usize,
+    pub timeouts: usize,
+}
+
+impl GameRow {
+    pub fn new(map: &str, result: &PunterScore) -> GameRow {
+        GameRow {
+            map: map.to_string(),
+            settings: result.settings.clone(),
+            game: result.battle,
+            seat: result.seat,
+            bot: result.bot_name.clone(),
+            score: result.score,
+            points: result.point,
+            moves: result.move_count,
+            millis: millis(result.consumed_time),
+            errors: result.errors,
+            timeouts: result.timeouts,
+        }
+    }
+}
+
+// A bot's games of a whole run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SummaryRow {
+    pub bot: String,
+    pub games: usize,
+    pub mean_score: f64,
+    pub mean_points: f64,
+    pub moves: u64,
+    pub millis: u64,
+    pub errors: usize,
+    pub timeouts: usize,
+}
+
+pub fn summarize(rows: &[GameRow]) -> Vec<SummaryRow> {
+    let mut by_bot: BTreeMap<&str, Vec<&GameRow>> = BTreeMap::new();
+    for row in rows {
+        by_bot.entry(&row.bot).or_insert_with(Vec::new).push(row);
+    }
+    by_bot
+        .into_iter()
+        .map(|(bot, rows)| {
+            let games = rows.len();
+            SummaryRow {
+                bot: bot.to_string(),
+                games,
+                mean_score: rows.iter().map(|r| r.score).sum::<i64>() as f64 / games as f64,
+                mean_points: rows.iter().map(|r| r.points).sum::<usize>() as f64 / games as f64,
+                moves: rows.iter().map(|r| r.moves).sum(),
+                millis: rows.iter().map(|r| r.millis).sum(),
+                errors: rows.iter().map(|r| r.errors).sum(),
+                timeouts: rows.iter().map(|r| r.timeouts).sum(),
+            }
+        })
+        .collect()
+}
+
+// Quotes a field if it has a comma, a quote or a line break, as in RFC 4180.
+fn csv_field(s: &str) -> String {
+    if s.contains(|c: char| c == ',' || c == '"' || c == '\n' || c == '\r') {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn game_csv(rows: &[GameRow]) -> String {
+    let mut csv = "map,settings,game,seat,bot,score,points,moves,millis,errors,timeouts\n".to_string();
+    for r in rows {
+        csv += &format!(
+            "{},{},{},{},{},{},{},{},{},{},{}\n",
+            csv_field(&r.map),
+            csv_field(&r.settings),
+            r.game,
+            r.seat,
+            csv_field(&r.bot),
+            r.score,
+            r.points,
+            r.moves,
+            r.millis,
+            r.errors,
+            r.timeouts
+        );
+    }
+    csv
+}
+
+fn summary_csv(rows: &[SummaryRow]) -> String {
+    let mut csv = "bot,games,mean_score,mean_points,moves,millis,errors,timeouts\n".to_string();
+    for r in rows {
+        csv += &format!(
+            "{},{},{:.3},{:.3},{},{},{},{}\n",
+            csv_field(&r.bot),
+            r.games,
+            r.mean_score,
+            r.mean_points,
+            r.moves,
+            r.millis,
+            r.errors,
+            r.timeouts
+        );
+    }
+    csv
+}
+
+// Writes games.csv, games.json, summary.csv and summary.json into `dir`. Rows are sorted by game and seat, so that
+// seeded runs can be diffed.
+pub fn write<P: AsRef<Path>>(dir: P, rows: &mut [GameRow]) -> PunterResult<()> {
+    let dir = dir.as_ref();
+    fs::create_dir_all(dir)?;
+    rows.sort_by_key(|r| (r.game, r.seat));
+    let summary = summarize(rows);
+    fs::File::create(dir.join("games.csv"))?.write_all(game_csv(rows).as_bytes())?;
+    serde_json::to_writer_pretty(fs::File::create(dir.join("games.json"))?, &*rows)?;
+    fs::File::create(dir.join("summary.csv"))?.write_all(summary_csv(&summary).as_bytes())?;
+    serde_json::to_writer_pretty(fs::File::create(dir.join("summary.json"))?, &summary)?;
+    info!("exported {} rows into {}", rows.len(), dir.display());
+    Ok(())
+}
+
+#[test]
+fn export_test() {
+    let row = |game, seat, bot: &str, score, points| GameRow {
+        map: "lambda".to_string(),
+        settings: "none".to_string(),
+        game,
+        seat,
+        bot: bot.to_string(),
+        score,
+        points,
+        moves: 10,
+        millis: 5,
+        errors: 0,
+        timeouts: game,
+    };
+    let rows = vec![row(0, 0, "a", 30, 2), row(1, 0, "x,\"y\"", 10, 1), row(1, 1, "a", 20, 2)];
+
+    let csv = game_csv(&rows);
+    let lines = csv.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 4);
+    assert_eq!(lines[1], "lambda,none,0,0,a,30,2,10,5,0,0");
+    assert_eq!(lines[2], "lambda,none,1,0,\"x,\"\"y\"\"\",10,1,10,5,0,1");
+    let json = serde_json::to_string(&rows).unwrap();
+    assert_eq!(serde_json::from_str::<Vec<GameRow>>(&json).unwrap(), rows);
+
+    let summary = summarize(&rows);
+    assert_eq!(summary.len(), 2);
+    assert_eq!(summary[0].bot, "a");
+    assert_eq!((summary[0].games, summary[0].mean_score, summary[0].timeouts), (2, 25.0, 1));
+    assert!(summary_csv(&summary).contains("\na,2,25.000,2.000,20,10,0,1\n"));
+}
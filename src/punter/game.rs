@@ -1,13 +1,18 @@
 use base64;
 use bincode;
+use punter::budget::{TimeBudget, TimeControl, millis};
+use punter::opponent::OpponentModel;
+use punter::parallel;
+use punter::params::EdgeWeightParams;
 use punter::prelude::*;
 use punter::protocol::*;
-use std::cell::RefCell;
+use punter::search;
+use rayon::prelude::*;
 use std::cmp;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::collections::VecDeque;
-use std::rc::Rc;
+use std::str;
 use std::time::Instant;
 
 pub type Node = usize;
@@ -25,10 +30,12 @@ impl Edge {
         self.claimed.is_empty()
     }
 
-    pub fn claim(&mut self, me: PunterId, is_option: bool) {
+    pub fn claim(&mut self, me: PunterId, is_option: bool) -> bool {
         if self.claimed.claim(me, is_option).is_err() {
             warn!("double claiming for {:?} by {}", self, me);
+            return false;
         }
+        true
     }
 }
 
@@ -42,9 +49,12 @@ pub struct Game {
     extension: GameExtension,
     // Pre-computed values
     site_id_to_node: HashMap<SiteId, Node>,
-    edge_st_to_edge_index: HashMap<(Node, Node), EdgeIndex>,
+    edge_st_to_edge_index: HashMap<(Node, Node), Vec<EdgeIndex>>, // Parallel rivers share a key
     adj_edges: Vec<Vec<AdjEdge>>,
     dist_from_mine: Vec<Vec<usize>>, // dist[0][3] -> dist(mines[0], node3)
+    pub time_control: TimeControl,
+    pub params: EdgeWeightParams,
+    history: Vec<(PunterId, EdgeIndex)>, // Successful claims and options, oldest first
 }
 
 #[derive(Debug, Default, Serialize, Deserialize, Clone)]
@@ -89,6 +99,13 @@ impl Claimed {
             Claimed::Optioned(_, _) => false,
         }
     }
+    pub fn is_owned_by(&self, p: PunterId) -> bool {
+        match *self {
+            Claimed::NotYet => false,
+            Claimed::Claimed(p0) => p0 == p,
+            Claimed::Optioned(p0, p1) => p0 == p || p1 == p,
+        }
+    }
     pub fn claim(&mut self, me: PunterId, is_option: bool) -> Result<(), ()> {
         match *self {
             Claimed::NotYet => {
@@ -152,7 +169,7 @@ impl From<SetupSP> for Game {
         let edge_st_to_edge_index = {
             let mut st_to_index = HashMap::new();
             for (index, edge) in edges.iter().enumerate() {
-                st_to_index.insert((edge.source, edge.target), index);
+                st_to_index.entry((edge.source, edge.target)).or_insert_with(Vec::new).push(index);
             }
             st_to_index
         };
@@ -220,7 +237,7 @@ impl From<SetupSP> for Game {
                 is_splurge_on: setup.settings.as_ref().and_then(|s| s.splurge).unwrap_or(
                     false,
                 ),
-                is_options_on: setup.settings.as_ref().and_then(|s| s.splurge).unwrap_or(
+                is_options_on: setup.settings.as_ref().and_then(|s| s.options).unwrap_or(
                     false,
                 ),
                 futures: Vec::new(),
@@ -231,6 +248,9 @@ impl From<SetupSP> for Game {
             edge_st_to_edge_index,
             adj_edges,
             dist_from_mine,
+            time_control: Default::default(),
+            params: Default::default(),
+            history: Vec::new(),
         }
     }
 }
@@ -258,12 +278,124 @@ struct EdgeCandidate {
     target: Node,
 }
 
-type EdgeWeights = Vec<u64>;
+pub type EdgeWeights = Vec<f64>;
+
+fn cmp_weight(a: f64, b: f64) -> cmp::Ordering {
+    a.partial_cmp(&b).unwrap_or(cmp::Ordering::Equal)
+}
+
+// Buffers for a BFS tree rooted at a mine, reused across mines.
+struct BfsTree {
+    order: Vec<Node>,
+    visited: Vec<bool>,
+    parent: Vec<(Node, EdgeIndex)>,
+    promise: Vec<f64>,
+    is_mine: Vec<bool>,
+}
+
+impl BfsTree {
+    fn new(nodes: usize, mines: &[Node]) -> BfsTree {
+        let mut is_mine = vec![false; nodes];
+        for mine in mines {
+            is_mine[*mine] = true;
+        }
+        BfsTree {
+            order: Vec::with_capacity(nodes),
+            visited: vec![false; nodes],
+            parent: vec![(0, 0); nodes],
+            promise: vec![0.0; nodes],
+            is_mine,
+        }
+    }
+}
 
 #[derive(Debug, Copy, Clone)]
 pub enum Strategy {
     Stupid,
     EdgeWeight,
+    Greedy,
+    Minimax,
+    // EdgeWeight, switching to the exact endgame search once only a few rivers matter.
+    Endgame,
+}
+
+impl str::FromStr for Strategy {
+    type Err = PunterError;
+
+    fn from_str(s: &str) -> PunterResult<Strategy> {
+        match s {
+            "Stupid" => Ok(Strategy::Stupid),
+            "EdgeWeight" => Ok(Strategy::EdgeWeight),
+            "Greedy" => Ok(Strategy::Greedy),
+            "Minimax" => Ok(Strategy::Minimax),
+            "Endgame" => Ok(Strategy::Endgame),
+            _ => Err(PunterError::InvalidArgument(format!("unknown strategy: {}", s))),
+        }
+    }
+}
+
+impl Strategy {
+    fn uses_endgame(&self) -> bool {
+        match *self {
+            Strategy::Stupid | Strategy::EdgeWeight => false,
+            Strategy::Greedy | Strategy::Minimax | Strategy::Endgame => true,
+        }
+    }
+}
+
+// Connected components of the rivers owned by a punter, with per-mine score sums for each component.
+// Claiming a river merges (at most) two components, so the exact score change of a claim can be
+// computed without re-running BFS from every mine.
+#[derive(Debug, Clone)]
+pub struct Connectivity {
+    component: Vec<usize>, // node -> component
+    mine_components: Vec<usize>, // mines[i] -> component
+    component_scores: Vec<Vec<i64>>, // [component][i] -> sum of dist(mines[i], node)^2
+    futures: Vec<Option<(usize, i64)>>, // mines[i] -> (component of the future's target, dist^3)
+}
+
+impl Connectivity {
+    pub fn is_connected_to_mine(&self, node: Node) -> bool {
+        self.mine_components.contains(&self.component[node])
+    }
+
+    // Mines whose network has at least one river.
+    pub fn active_mines(&self, game: &Game) -> Vec<Node> {
+        game.mines
+            .iter()
+            .zip(self.mine_components.iter())
+            .filter(|&(mine, c)| {
+                game.adj_edges[*mine].iter().any(|adj| self.component[adj.target] == *c)
+            })
+            .map(|(mine, _)| *mine)
+            .collect()
+    }
+
+    pub fn score_delta(&self, edge: &Edge) -> i64 {
+        let cs = self.component[edge.source];
+        let ct = self.component[edge.target];
+        if cs == ct {
+            return 0;
+        }
+        let mut delta = 0;
+        for (i, &cm) in self.mine_components.iter().enumerate() {
+            let other = if cm == cs {
+                ct
+            } else if cm == ct {
+                cs
+            } else {
+                continue;
+            };
+            delta += self.component_scores[other][i];
+            if let Some((future_component, d3)) = self.futures[i] {
+                if future_component == other {
+                    // -d^3 becomes +d^3.
+                    delta += 2 * d3;
+                }
+            }
+        }
+        delta
+    }
 }
 
 impl Game {
@@ -289,6 +421,44 @@ impl Game {
             .collect();
     }
 
+    pub fn punters(&self) -> usize {
+        self.punters
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    pub fn site_count(&self) -> usize {
+        self.site_ids.len()
+    }
+
+    pub fn mines(&self) -> &[Node] {
+        &self.mines
+    }
+
+    pub fn adj_edges(&self, node: Node) -> &[AdjEdge] {
+        &self.adj_edges[node]
+    }
+
+    pub fn history(&self) -> &[(PunterId, EdgeIndex)] {
+        &self.history
+    }
+
+    // Claims and releases rivers without any validation. Used by search to make and unmake moves.
+    pub fn claim_edge(&mut self, index: EdgeIndex, p: PunterId) {
+        self.edges[index].claimed = Claimed::Claimed(p);
+    }
+
+    pub fn unclaim_edge(&mut self, index: EdgeIndex) {
+        self.edges[index].claimed = Claimed::NotYet;
+    }
+
+    // Our futures, in the order of mines().
+    pub fn futures(&self) -> &[Node] {
+        &self.extension.futures
+    }
+
     pub fn is_futures_on(&self) -> bool {
         self.extension.is_futures_on
     }
@@ -326,7 +496,7 @@ impl Game {
         EdgeClaim::new(claim.punter, s, t)
     }
 
-    fn node_to_site_id(&self, i: Node) -> SiteId {
+    pub fn node_to_site_id(&self, i: Node) -> SiteId {
         self.site_ids[i]
     }
 
@@ -335,21 +505,32 @@ impl Game {
         self.apply_edge_claim(c, false);
     }
 
+    // A claim can not tell parallel rivers apart, so it takes the first one which can be claimed.
+    fn claimable_edge(&self, claim: &EdgeClaim, is_option: bool) -> Option<EdgeIndex> {
+        self.edge_st_to_edge_index.get(&(claim.source, claim.target)).and_then(|indices| {
+            indices.iter().cloned().find(|index| match self.edges[*index].claimed {
+                Claimed::NotYet => !is_option,
+                Claimed::Claimed(p) => is_option && p != claim.punter,
+                Claimed::Optioned(_, _) => false,
+            })
+        })
+    }
+
     pub fn apply_edge_claim(&mut self, claim: EdgeClaim, is_option: bool) {
         debug!("edge-claim: {:?}", claim);
         assert!(claim.source < claim.target);
-        match self.edge_st_to_edge_index.get(
-            &(claim.source, claim.target),
-        ) {
-            Some(index) => {
-                let edge: &mut Edge = &mut self.edges[*index];
-                assert_eq!(claim.source, edge.source);
-                assert_eq!(claim.target, edge.target);
-                edge.claim(claim.punter, is_option);
-            }
+        let index = match self.edge_st_to_edge_index.get(&(claim.source, claim.target)) {
+            Some(indices) => self.claimable_edge(&claim, is_option).unwrap_or(indices[0]),
             None => {
                 warn!("invalid claim: {:?}", claim);
+                return;
             }
+        };
+        let edge: &mut Edge = &mut self.edges[index];
+        assert_eq!(claim.source, edge.source);
+        assert_eq!(claim.target, edge.target);
+        if edge.claim(claim.punter, is_option) {
+            self.history.push((claim.punter, index));
         }
     }
 
@@ -359,21 +540,42 @@ impl Game {
         self.apply_edge_claim(c, true);
     }
 
-    pub fn play(&self, strategy: Strategy) -> Move {
+    pub fn play(&self, strategy: Strategy, budget: &TimeBudget) -> Move {
         let now = Instant::now();
-        let edge_claim = {
-            match strategy {
-                Strategy::Stupid => self.play_stupid(),
-                Strategy::EdgeWeight => self.play_edge_weight(),
+        let endgame = if strategy.uses_endgame() {
+            search::play_endgame(self, budget)
+        } else {
+            None
+        };
+        let edge_claim = match endgame {
+            Some(edge_claim) => edge_claim,
+            None => {
+                match strategy {
+                    Strategy::Stupid => self.play_stupid(),
+                    Strategy::EdgeWeight | Strategy::Endgame => self.play_edge_weight(),
+                    Strategy::Greedy => self.play_greedy(),
+                    Strategy::Minimax => search::play_minimax(self, budget),
+                }
             }
         };
-        let secs = now.elapsed().as_secs();
-        if secs >= 1 {
-            warn!("{} secs passed", secs);
+        if budget.is_over() {
+            warn!("{:?} exceeded its time budget: {}ms", strategy, millis(now.elapsed()));
         }
         Move::from(self.convert_to_claim(edge_claim))
     }
 
+    // A cheap and safe move: the first free river adjacent to our network, or any free river.
+    pub fn play_fallback(&self) -> Move {
+        let connectivity = self.connectivity(self.me);
+        let adjacent = self.edges.iter().find(|e| {
+            e.is_empty() && (connectivity.is_connected_to_mine(e.source) || connectivity.is_connected_to_mine(e.target))
+        });
+        match adjacent.or_else(|| self.edges.iter().find(|e| e.is_empty())) {
+            Some(e) => Move::from(self.convert_to_claim(EdgeClaim::new(self.me, e.source, e.target))),
+            None => Move::Pass { pass: Pass { punter: self.me } },
+        }
+    }
+
     fn play_stupid(&self) -> EdgeClaim {
         let empty_edge = self.edges.iter().find(|e| e.is_empty()).unwrap();
         EdgeClaim::new(self.me, empty_edge.source, empty_edge.target)
@@ -399,81 +601,150 @@ impl Game {
     }
 
     fn find_valuable_edge_by_weight(&self) -> EdgeClaim {
-        let edge_weights: Rc<RefCell<EdgeWeights>> = Rc::new(RefCell::new(vec![0; self.edges.len()]));
-        for (mine, dist_from_mine) in self.mines.iter().zip(self.dist_from_mine.iter()) {
-            self.calc_edge_weight_for(edge_weights.clone(), *mine, dist_from_mine);
-        }
-        assert_eq!(Rc::strong_count(&edge_weights), 1);
-        Rc::try_unwrap(edge_weights)
-            .unwrap()
-            .into_inner()
+        self.calc_edge_weights(self.me)
             .into_iter()
             .zip(self.edges.iter())
             .filter(|&(_, e)| e.is_empty())
-            .max_by_key(|&(weight, _)| weight)
+            .max_by(|a, b| cmp_weight(a.0, b.0))
             .map(|(_, e)| EdgeClaim::new(self.me, e.source, e.target))
             .unwrap()
     }
 
-    fn edge_index(&self, s: usize, t: usize) -> usize {
-        self.edge_st_to_edge_index[&(cmp::min(s, t), cmp::max(s, t))]
-    }
+    // EdgeWeight potential of each river from punter p's point of view.
+    pub fn calc_edge_weights(&self, p: PunterId) -> EdgeWeights {
+        let mut edge_weights = if parallel::is_enabled() && self.mines.len() > 1 {
+            // Each worker accumulates the weights of its mines into its own buffers.
+            (0..self.mines.len())
+                .into_par_iter()
+                .fold(
+                    || (vec![0.0; self.edges.len()], BfsTree::new(self.site_ids.len(), &self.mines)),
+                    |(mut edge_weights, mut tree), i| {
+                        self.calc_edge_weight_for(&mut edge_weights, &mut tree, p, i);
+                        (edge_weights, tree)
+                    },
+                )
+                .map(|(edge_weights, _)| edge_weights)
+                .reduce(|| vec![0.0; self.edges.len()], |mut a, b| {
+                    for (x, y) in a.iter_mut().zip(b.into_iter()) {
+                        *x += y;
+                    }
+                    a
+                })
+        } else {
+            let mut edge_weights = vec![0.0; self.edges.len()];
+            let mut tree = BfsTree::new(self.site_ids.len(), &self.mines);
+            for i in 0..self.mines.len() {
+                self.calc_edge_weight_for(&mut edge_weights, &mut tree, p, i);
+            }
+            edge_weights
+        };
 
-    fn calc_edge_weight_for(&self, edge_weights: Rc<RefCell<EdgeWeights>>, mine: Node, dist_from_mine: &[usize]) {
-        #[derive(Debug, Clone, PartialEq)]
-        struct Entry {
-            source: Node,
-            weight_promise: u64,
-            prev: Option<(EdgeIndex, Rc<RefCell<Entry>>)>,
-            edge_weights: Rc<RefCell<EdgeWeights>>,
+        if self.params.contention_penalty != 0.0 {
+            let opponent_rivers = self.adj_edges
+                .iter()
+                .map(|adj_edges| {
+                    adj_edges
+                        .iter()
+                        .filter(|adj| {
+                            let claimed = &self.edges[adj.edge_index].claimed;
+                            !claimed.is_empty() && !claimed.is_owned_by(p)
+                        })
+                        .count()
+                })
+                .collect::<Vec<_>>();
+            for (weight, e) in edge_weights.iter_mut().zip(self.edges.iter()) {
+                let n = (opponent_rivers[e.source] + opponent_rivers[e.target]) as f64;
+                *weight *= (-self.params.contention_penalty * n).exp();
+            }
         }
 
-        impl Drop for Entry {
-            fn drop(&mut self) {
-                match self.prev {
-                    Some((edge_index, ref prev_entry)) => {
-                        self.edge_weights.borrow_mut()[edge_index] += self.weight_promise;
-                        prev_entry.borrow_mut().weight_promise += self.weight_promise;
-                    }
-                    None => {}
-                }
+        if self.params.contested_bonus != 0.0 && p == self.me {
+            let model = OpponentModel::new(self);
+            for (index, weight) in edge_weights.iter_mut().enumerate() {
+                *weight *= 1.0 + self.params.contested_bonus * model.contention(index);
             }
         }
+        edge_weights
+    }
 
-        let mut q = VecDeque::new();
-        q.push_back(Rc::new(RefCell::new(Entry {
-            source: mine,
-            weight_promise: 0,
-            prev: None,
-            edge_weights: edge_weights,
-        })));
-        let mut visited = HashSet::new();
-        visited.insert(mine);
+    // Claims the river adjacent to our network which increases our score the most.
+    // Ties are broken by the EdgeWeight potential.
+    fn play_greedy(&self) -> EdgeClaim {
+        let connectivity = self.connectivity(self.me);
+        let edge_weights = self.calc_edge_weights(self.me);
+        let candidates = self.edges
+            .iter()
+            .zip(edge_weights.into_iter())
+            .filter(|&(e, _)| {
+                e.is_empty() && (connectivity.is_connected_to_mine(e.source) || connectivity.is_connected_to_mine(e.target))
+            })
+            .collect::<Vec<_>>();
+        parallel::map(candidates, |(e, weight)| (e, connectivity.score_delta(e), weight))
+            .into_iter()
+            .max_by(|a, b| a.1.cmp(&b.1).then(cmp_weight(a.2, b.2)))
+            .map(|(e, _, _)| EdgeClaim::new(self.me, e.source, e.target))
+            .unwrap_or_else(|| self.find_valuable_edge_by_weight())
+    }
+
+    // Points a site at distance d from a mine promises. By default this is d^2.
+    fn site_promise(&self, d: usize, is_mine: bool, is_future: bool) -> f64 {
+        let d = d as f64;
+        let mut promise = if self.params.distance_exponent == 2.0 {
+            d * d
+        } else {
+            d.powf(self.params.distance_exponent)
+        };
+        if is_mine {
+            promise *= 1.0 + self.params.mine_link_bonus;
+        }
+        if is_future {
+            promise += self.params.futures_bonus * d * d * d;
+        }
+        promise
+    }
+
+    // Each site reachable from mines[i] promises some points. The promise flows to every river on the
+    // site's path in the BFS tree, so the weight of a river is the sum of promises of its subtree.
+    fn calc_edge_weight_for(&self, edge_weights: &mut EdgeWeights, tree: &mut BfsTree, p: PunterId, i: usize) {
+        let mine = self.mines[i];
+        let dist_from_mine = &self.dist_from_mine[i];
+        let future = if p == self.me {
+            self.extension.futures.get(i).cloned()
+        } else {
+            None
+        };
+
+        tree.order.clear();
+        tree.order.push(mine);
+        tree.visited[mine] = true;
+        tree.promise[mine] = 0.0;
 
-        while let Some(entry) = q.pop_front() {
-            let source = entry.borrow().source;
+        let mut head = 0;
+        while head < tree.order.len() {
+            let source = tree.order[head];
+            head += 1;
             for adj in self.adj_edges[source].iter() {
                 let target = adj.target;
-                if visited.contains(&target) {
+                if tree.visited[target] || !(self.edges[adj.edge_index].is_empty() || self.edges[adj.edge_index].claimed.is_owned_by(p)) {
                     continue;
                 }
-
-                if match self.edges[adj.edge_index].claimed {
-                    Claimed::NotYet => true,
-                    Claimed::Claimed(p) => p == self.me,
-                    Claimed::Optioned(p0, p1) => p0 == self.me || p1 == self.me,
-                }
-                {
-                    visited.insert(target);
-                    q.push_back(Rc::new(RefCell::new(Entry {
-                        source: adj.target,
-                        weight_promise: (dist_from_mine[target] * dist_from_mine[target]) as u64,
-                        prev: Some((self.edge_index(source, target), entry.clone())),
-                        edge_weights: entry.borrow().edge_weights.clone(),
-                    })));
-                }
+                tree.visited[target] = true;
+                tree.parent[target] = (source, adj.edge_index);
+                tree.promise[target] = self.site_promise(dist_from_mine[target], tree.is_mine[target], future == Some(target));
+                tree.order.push(target);
             }
         }
+
+        // Children come after their parents in BFS order, so a reverse scan sees complete subtrees.
+        for &node in tree.order.iter().skip(1).rev() {
+            let (parent, edge_index) = tree.parent[node];
+            let promise = tree.promise[node];
+            edge_weights[edge_index] += promise;
+            tree.promise[parent] += promise;
+        }
+        for &node in tree.order.iter() {
+            tree.visited[node] = false;
+        }
     }
 
     pub fn apply_move(&mut self, m: Move) {
@@ -495,8 +766,29 @@ impl Game {
         }
     }
 
+    // Whether a move can be played now, ignoring which extensions are on. A splurge using the same river
+    // twice is not detected.
+    pub fn is_legal_move(&self, m: &Move) -> bool {
+        let claimable = |punter, source, target, is_option| {
+            match (self.site_id_to_node.get(&source), self.site_id_to_node.get(&target)) {
+                (Some(s), Some(t)) => self.claimable_edge(&EdgeClaim::new(punter, *s, *t), is_option).is_some(),
+                _ => false,
+            }
+        };
+        match *m {
+            Move::Claim { ref claim } => claimable(claim.punter, claim.source, claim.target, false),
+            Move::Option_ { ref option } => claimable(option.punter, option.source, option.target, true),
+            Move::Splurge { ref splurge } => {
+                splurge.route.len() >= 2 &&
+                    splurge.route.windows(2).all(|r| claimable(splurge.punter, r[0], r[1], false))
+            }
+            Move::Pass { .. } => true,
+        }
+    }
+
     pub fn apply_moves_excluding_me(&mut self, moves: Vec<Move>) {
         let me = self.me;
+        self.time_control.check_my_last_move(me, &moves);
         for m in moves.into_iter().filter(|m| !m.claimed_by(me)) {
             self.apply_move(m);
         }
@@ -518,6 +810,79 @@ impl Game {
             .sum()
     }
 
+    // Score of futures for punter p: +d^3 for each future whose target is connected to its mine, -d^3
+    // otherwise. Unlike score(), this works for any punter's futures.
+    pub fn futures_score(&self, p: PunterId, futures: &[Future]) -> i64 {
+        let connectivity = self.connectivity(p);
+        futures
+            .iter()
+            .filter_map(|future| {
+                let mine = self.site_id_to_node.get(&future.source);
+                let target = self.site_id_to_node.get(&future.target);
+                let i = mine.and_then(|mine| self.mines.iter().position(|m| m == mine));
+                match (mine, target, i) {
+                    (Some(mine), Some(target), Some(i)) => {
+                        let d = self.dist_from_mine[i][*target] as i64;
+                        Some(if connectivity.component[*mine] == connectivity.component[*target] {
+                            d * d * d
+                        } else {
+                            -d * d * d
+                        })
+                    }
+                    _ => None,
+                }
+            })
+            .sum()
+    }
+
+    pub fn connectivity(&self, p: PunterId) -> Connectivity {
+        let mut component = vec![usize::max_value(); self.site_ids.len()];
+        let mut components = 0;
+        for start in 0..self.site_ids.len() {
+            if component[start] != usize::max_value() {
+                continue;
+            }
+            component[start] = components;
+            let mut q = VecDeque::new();
+            q.push_back(start);
+            while let Some(s) = q.pop_front() {
+                for adj in self.adj_edges[s].iter() {
+                    if component[adj.target] == usize::max_value() && self.edges[adj.edge_index].claimed.is_owned_by(p) {
+                        component[adj.target] = components;
+                        q.push_back(adj.target);
+                    }
+                }
+            }
+            components += 1;
+        }
+
+        let mut component_scores = vec![vec![0; self.mines.len()]; components];
+        for (node, c) in component.iter().enumerate() {
+            for (i, dist_from_mine) in self.dist_from_mine.iter().enumerate() {
+                let d = dist_from_mine[node] as i64;
+                component_scores[*c][i] += d * d;
+            }
+        }
+
+        let futures = (0..self.mines.len())
+            .map(|i| if p == self.me {
+                self.extension.futures.get(i).map(|&target| {
+                    let d = self.dist_from_mine[i][target] as i64;
+                    (component[target], d * d * d)
+                })
+            } else {
+                None
+            })
+            .collect();
+
+        Connectivity {
+            mine_components: self.mines.iter().map(|mine| component[*mine]).collect(),
+            component,
+            component_scores,
+            futures,
+        }
+    }
+
     fn score_for_mine(&self, mine: Node, dist_from_mine: &[usize], future: Option<Node>, p: PunterId) -> i64 {
         let mut q = VecDeque::new();
         q.push_back(mine);
@@ -592,3 +957,75 @@ impl Game {
         }
     }
 }
+
+#[test]
+fn score_delta_test() {
+    let map: Map = ::serde_json::from_str(
+        r#"{"sites": [{"id": 0, "x": 0.0, "y": 0.0}, {"id": 1, "x": 1.0, "y": 0.0}, {"id": 2, "x": 2.0, "y": 0.0},
+                      {"id": 3, "x": 2.0, "y": -1.0}, {"id": 4, "x": 2.0, "y": -2.0}, {"id": 5, "x": 1.0, "y": -2.0},
+                      {"id": 6, "x": 0.0, "y": -2.0}, {"id": 7, "x": 0.0, "y": -1.0}],
+            "rivers": [{"source": 0, "target": 1}, {"source": 1, "target": 2}, {"source": 0, "target": 7},
+                       {"source": 7, "target": 6}, {"source": 6, "target": 5}, {"source": 5, "target": 4},
+                       {"source": 4, "target": 3}, {"source": 3, "target": 2}, {"source": 1, "target": 7},
+                       {"source": 1, "target": 3}, {"source": 7, "target": 5}, {"source": 5, "target": 3}],
+            "mines": [1, 5]}"#,
+    ).unwrap();
+    let mut game: Game = SetupSP {
+        punter: 0,
+        punters: 2,
+        map,
+        settings: Some(Settings {
+            futures: Some(true),
+            splurge: None,
+            options: None,
+        }),
+    }.into();
+    game.setup_futures();
+    for &(p, s, t) in [(0, 1, 7), (1, 5, 6), (0, 0, 7), (1, 3, 4)].iter() {
+        game.apply_edge_claim(EdgeClaim::new(p, s, t), false);
+    }
+
+    for p in 0..2 {
+        let connectivity = game.connectivity(p);
+        let score = game.score(p);
+        for e in game.edges.iter().filter(|e| e.is_empty()) {
+            let mut next = game.clone();
+            next.apply_edge_claim(EdgeClaim::new(p, e.source, e.target), false);
+            assert_eq!(connectivity.score_delta(e), next.score(p) - score);
+        }
+    }
+}
+
+#[test]
+fn parallel_rivers_test() {
+    let map: Map = ::serde_json::from_str(
+        r#"{"sites": [{"id": 0, "x": 0.0, "y": 0.0}, {"id": 1, "x": 1.0, "y": 0.0}, {"id": 2, "x": 2.0, "y": 0.0}],
+            "rivers": [{"source": 0, "target": 1}, {"source": 1, "target": 0}, {"source": 1, "target": 2}],
+            "mines": [0]}"#,
+    ).unwrap();
+    let mut game: Game = SetupSP {
+        punter: 0,
+        punters: 3,
+        map,
+        settings: None,
+    }.into();
+    // Each claim takes the next free river between 0 and 1. The third one has nothing to take.
+    for p in 0..3 {
+        game.apply_edge_claim(EdgeClaim::new(p, 0, 1), false);
+    }
+    assert_eq!(game.edges[0].claimed, Claimed::Claimed(0));
+    assert_eq!(game.edges[1].claimed, Claimed::Claimed(1));
+    assert_eq!(game.history().to_vec(), vec![(0, 0), (1, 1)]);
+    assert_eq!((game.score(0), game.score(1), game.score(2)), (1, 1, 0));
+    let claim = Claim {
+        punter: 2,
+        source: 1,
+        target: 0,
+    };
+    assert!(!game.is_legal_move(&Move::Claim { claim: claim.clone() }));
+    assert!(game.is_legal_move(&Move::Option_ { option: claim }));
+
+    game.apply_edge_claim(EdgeClaim::new(1, 1, 2), false);
+    assert_eq!(game.score(1), 1 + 4);
+    assert!(search::relevant_free_rivers(&game).is_empty());
+}
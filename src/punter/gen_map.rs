@@ -0,0 +1,412 @@
+use punter::prelude::*;
+use punter::protocol::*;
+use rand::{Rng, SeedableRng, StdRng};
+use serde_json;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::PI;
+use std::fs;
+use std::str;
+
+// Sites are placed in a square of this size.
+const EXTENT: f64 = 100.0;
+// Average degree of random geometric maps.
+const GEOMETRIC_DEGREE: f64 = 5.0;
+// Probability that a site of a random sparse map also links to its second nearest site.
+const SPARSE_SECOND_LINK: f64 = 0.4;
+// Rivers from each site to the nearest sites of its own city.
+const CITY_LINKS: usize = 3;
+// Extra rivers between random pairs of cities, per city.
+const CITY_EXTRA_BRIDGES: f64 = 0.5;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MapFamily {
+    Grid,
+    Geometric,
+    Sparse,
+    Sierpinski,
+    Cities,
+    Tree,
+}
+
+impl str::FromStr for MapFamily {
+    type Err = PunterError;
+
+    fn from_str(s: &str) -> PunterResult<MapFamily> {
+        match s {
+            "grid" => Ok(MapFamily::Grid),
+            "geometric" => Ok(MapFamily::Geometric),
+            "sparse" => Ok(MapFamily::Sparse),
+            "sierpinski" => Ok(MapFamily::Sierpinski),
+            "cities" => Ok(MapFamily::Cities),
+            "tree" => Ok(MapFamily::Tree),
+            _ => Err(PunterError::InvalidArgument(format!("unknown map family: {}", s))),
+        }
+    }
+}
+
+impl MapFamily {
+    pub fn names() -> &'static [&'static str] {
+        &["grid", "geometric", "sparse", "sierpinski", "cities", "tree"]
+    }
+}
+
+// Generates connected maps without duplicate rivers or self-loops.
+#[derive(Debug, Clone)]
+pub struct MapGenerator {
+    pub family: MapFamily,
+    pub size: usize, // the number of sites, roughly for grid and sierpinski
+    pub mines: usize, // also the number of cities
+    pub seed: u64,
+}
+
+struct Builder {
+    sites: Vec<(f64, f64)>,
+    rivers: Vec<(usize, usize)>,
+    river_set: HashSet<(usize, usize)>,
+}
+
+impl Builder {
+    fn new() -> Builder {
+        Builder {
+            sites: vec![],
+            rivers: vec![],
+            river_set: HashSet::new(),
+        }
+    }
+
+    fn add_site(&mut self, x: f64, y: f64) -> usize {
+        self.sites.push((x, y));
+        self.sites.len() - 1
+    }
+
+    fn add_river(&mut self, s: usize, t: usize) {
+        let river = (cmp::min(s, t), cmp::max(s, t));
+        if s != t && self.river_set.insert(river) {
+            self.rivers.push(river);
+        }
+    }
+
+    fn distance2(&self, s: usize, t: usize) -> f64 {
+        let (dx, dy) = (self.sites[s].0 - self.sites[t].0, self.sites[s].1 - self.sites[t].1);
+        dx * dx + dy * dy
+    }
+
+    // Links each of `nodes` to its k nearest sites among `nodes`.
+    fn link_nearest(&mut self, nodes: &[usize], k: usize) {
+        for &s in nodes {
+            let mut others: Vec<(f64, usize)> = nodes.iter().filter(|t| **t != s).map(|t| (self.distance2(s, *t), *t)).collect();
+            others.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            for &(_, t) in others.iter().take(k) {
+                self.add_river(s, t);
+            }
+        }
+    }
+
+    fn components(&self) -> Vec<Vec<usize>> {
+        let mut adj = vec![vec![]; self.sites.len()];
+        for &(s, t) in self.rivers.iter() {
+            adj[s].push(t);
+            adj[t].push(s);
+        }
+        let mut visited = vec![false; self.sites.len()];
+        let mut components = vec![];
+        for root in 0..self.sites.len() {
+            if visited[root] {
+                continue;
+            }
+            visited[root] = true;
+            let mut component = vec![root];
+            let mut i = 0;
+            while i < component.len() {
+                let v = component[i];
+                i += 1;
+                for &w in adj[v].iter() {
+                    if !visited[w] {
+                        visited[w] = true;
+                        component.push(w);
+                    }
+                }
+            }
+            components.push(component);
+        }
+        components
+    }
+
+    // Links every component to the nearest site of the components before it, so that the map is connected.
+    fn connect(&mut self) {
+        let components = self.components();
+        let mut connected: Vec<usize> = components[0].clone();
+        for component in components.iter().skip(1) {
+            let mut nearest = None;
+            for &s in component.iter() {
+                for &t in connected.iter() {
+                    let d = self.distance2(s, t);
+                    if nearest.map_or(true, |(best, _, _)| d < best) {
+                        nearest = Some((d, s, t));
+                    }
+                }
+            }
+            let (_, s, t) = nearest.unwrap();
+            self.add_river(s, t);
+            connected.extend(component.iter().cloned());
+        }
+    }
+
+    fn build(self, mines: Vec<usize>) -> Map {
+        Map {
+            sites: self.sites
+                .iter()
+                .enumerate()
+                .map(|(id, &(x, y))| {
+                    Site {
+                        id: id as SiteId,
+                        x,
+                        y,
+                    }
+                })
+                .collect(),
+            rivers: self.rivers
+                .iter()
+                .map(|&(s, t)| {
+                    River {
+                        source: s as SiteId,
+                        target: t as SiteId,
+                    }
+                })
+                .collect(),
+            mines: mines.into_iter().map(|m| m as SiteId).collect(),
+        }
+    }
+}
+
+impl MapGenerator {
+    pub fn generate(&self) -> Map {
+        let mut rng = StdRng::from_seed(&[self.seed as usize][..]);
+        let n = cmp::max(self.size, 2);
+        let mut builder = Builder::new();
+        let groups = match self.family {
+            MapFamily::Grid => grid(&mut builder, n),
+            MapFamily::Geometric => geometric(&mut builder, &mut rng, n),
+            MapFamily::Sparse => sparse(&mut builder, &mut rng, n),
+            MapFamily::Sierpinski => sierpinski(&mut builder, n),
+            MapFamily::Cities => cities(&mut builder, &mut rng, n, cmp::max(self.mines, 2)),
+            MapFamily::Tree => tree(&mut builder, &mut rng, n),
+        };
+        builder.connect();
+        let mines = choose_mines(&mut rng, groups, self.mines);
+        builder.build(mines)
+    }
+}
+
+pub fn gen_map_run(generator: &MapGenerator, output: Option<&str>) -> PunterResult<()> {
+    let map = generator.generate();
+    match output {
+        Some(path) => serde_json::to_writer(fs::File::create(path)?, &map)?,
+        None => println!("{}", serde_json::to_string(&map)?),
+    }
+    Ok(())
+}
+
+// Picks mines from each group in turn.
+fn choose_mines(rng: &mut StdRng, mut groups: Vec<Vec<usize>>, count: usize) -> Vec<usize> {
+    for group in groups.iter_mut() {
+        rng.shuffle(group);
+    }
+    let mut mines = vec![];
+    let mut i = 0;
+    while mines.len() < count && groups.iter().any(|g| i < g.len()) {
+        for group in groups.iter() {
+            if mines.len() < count && i < group.len() {
+                mines.push(group[i]);
+            }
+        }
+        i += 1;
+    }
+    mines
+}
+
+fn random_point(rng: &mut StdRng) -> (f64, f64) {
+    (rng.gen::<f64>() * EXTENT, rng.gen::<f64>() * EXTENT)
+}
+
+fn grid(builder: &mut Builder, n: usize) -> Vec<Vec<usize>> {
+    let width = (n as f64).sqrt().ceil() as usize;
+    let height = (n + width - 1) / width;
+    for y in 0..height {
+        for x in 0..width {
+            builder.add_site(x as f64, y as f64);
+        }
+    }
+    for y in 0..height {
+        for x in 0..width {
+            let s = y * width + x;
+            if x + 1 < width {
+                builder.add_river(s, s + 1);
+            }
+            if y + 1 < height {
+                builder.add_river(s, s + width);
+            }
+        }
+    }
+    vec![(0..builder.sites.len()).collect()]
+}
+
+// Sites within a radius are linked, which gives GEOMETRIC_DEGREE rivers per site on average.
+fn geometric(builder: &mut Builder, rng: &mut StdRng, n: usize) -> Vec<Vec<usize>> {
+    for _ in 0..n {
+        let (x, y) = random_point(rng);
+        builder.add_site(x, y);
+    }
+    let radius2 = GEOMETRIC_DEGREE / (PI * n as f64) * EXTENT * EXTENT;
+    for s in 0..n {
+        for t in s + 1..n {
+            if builder.distance2(s, t) <= radius2 {
+                builder.add_river(s, t);
+            }
+        }
+    }
+    vec![(0..n).collect()]
+}
+
+fn sparse(builder: &mut Builder, rng: &mut StdRng, n: usize) -> Vec<Vec<usize>> {
+    for _ in 0..n {
+        let (x, y) = random_point(rng);
+        builder.add_site(x, y);
+    }
+    let nodes: Vec<usize> = (0..n).collect();
+    builder.link_nearest(&nodes, 1);
+    for s in 0..n {
+        if rng.gen::<f64>() < SPARSE_SECOND_LINK {
+            let mut others = nodes
+                .iter()
+                .filter(|t| **t != s)
+                .map(|t| (builder.distance2(s, *t), *t))
+                .collect::<Vec<_>>();
+            others.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            if let Some(&(_, t)) = others.get(1) {
+                builder.add_river(s, t);
+            }
+        }
+    }
+    vec![nodes]
+}
+
+// The deepest Sierpinski triangle with at most n sites (or the first one).
+fn sierpinski(builder: &mut Builder, n: usize) -> Vec<Vec<usize>> {
+    // Depth d has (3^(d + 1) + 3) / 2 sites.
+    let mut depth = 0;
+    while (3usize.pow(depth + 2) + 3) / 2 <= n {
+        depth += 1;
+    }
+    let side = 1i64 << (depth + 1);
+    let mut ids = HashMap::new();
+    let corners = [(0, 0), (side, 0), (side / 2, side / 2)];
+    sierpinski_triangle(builder, &mut ids, corners, depth);
+    vec![(0..builder.sites.len()).collect()]
+}
+
+fn sierpinski_triangle(
+    builder: &mut Builder,
+    ids: &mut HashMap<(i64, i64), usize>,
+    corners: [(i64, i64); 3],
+    depth: u32,
+) {
+    if depth == 0 {
+        let nodes: Vec<usize> = corners
+            .iter()
+            .map(|&(x, y)| *ids.entry((x, y)).or_insert_with(|| builder.add_site(x as f64, -y as f64)))
+            .collect();
+        builder.add_river(nodes[0], nodes[1]);
+        builder.add_river(nodes[1], nodes[2]);
+        builder.add_river(nodes[2], nodes[0]);
+        return;
+    }
+    let mid = |a: (i64, i64), b: (i64, i64)| ((a.0 + b.0) / 2, (a.1 + b.1) / 2);
+    let (a, b, c) = (corners[0], corners[1], corners[2]);
+    let (ab, bc, ca) = (mid(a, b), mid(b, c), mid(c, a));
+    sierpinski_triangle(builder, ids, [a, ab, ca], depth - 1);
+    sierpinski_triangle(builder, ids, [ab, b, bc], depth - 1);
+    sierpinski_triangle(builder, ids, [ca, bc, c], depth - 1);
+}
+
+// Dense clusters of sites joined by a few long rivers. Each city gets a mine before any city gets two.
+fn cities(builder: &mut Builder, rng: &mut StdRng, n: usize, count: usize) -> Vec<Vec<usize>> {
+    let count = cmp::min(count, cmp::max(n / 2, 1));
+    let spread = EXTENT / (count as f64).sqrt() / 6.0;
+    let centers: Vec<(f64, f64)> = (0..count).map(|_| random_point(rng)).collect();
+    let mut groups = vec![vec![]; count];
+    for i in 0..n {
+        let (cx, cy) = centers[i % count];
+        // Roughly normal around the center.
+        let dx = (rng.gen::<f64>() + rng.gen::<f64>() - 1.0) * spread;
+        let dy = (rng.gen::<f64>() + rng.gen::<f64>() - 1.0) * spread;
+        let s = builder.add_site(cx + dx, cy + dy);
+        groups[i % count].push(s);
+    }
+    for group in groups.iter() {
+        builder.link_nearest(group, CITY_LINKS);
+    }
+    let bridge = |builder: &mut Builder, a: &[usize], b: &[usize]| {
+        let mut nearest = None;
+        for &s in a {
+            for &t in b {
+                let d = builder.distance2(s, t);
+                if nearest.map_or(true, |(best, _, _)| d < best) {
+                    nearest = Some((d, s, t));
+                }
+            }
+        }
+        let (_, s, t) = nearest.unwrap();
+        builder.add_river(s, t);
+    };
+    for i in 1..count {
+        bridge(builder, &groups[i - 1], &groups[i]);
+    }
+    for _ in 0..(count as f64 * CITY_EXTRA_BRIDGES) as usize {
+        let (a, b) = (rng.gen_range(0, count), rng.gen_range(0, count));
+        if a != b {
+            bridge(builder, &groups[a], &groups[b]);
+        }
+    }
+    groups
+}
+
+// A random recursive tree: each site hangs off a random earlier site.
+fn tree(builder: &mut Builder, rng: &mut StdRng, n: usize) -> Vec<Vec<usize>> {
+    builder.add_site(EXTENT / 2.0, EXTENT / 2.0);
+    for i in 1..n {
+        let parent = rng.gen_range(0, i);
+        let (px, py) = builder.sites[parent];
+        let angle = rng.gen::<f64>() * 2.0 * PI;
+        let length = EXTENT / (n as f64).sqrt();
+        let s = builder.add_site(px + length * angle.cos(), py + length * angle.sin());
+        builder.add_river(parent, s);
+    }
+    vec![(0..n).collect()]
+}
+
+#[test]
+fn gen_map_test() {
+    use punter::map_info::MapInfo;
+
+    for name in MapFamily::names() {
+        let generator = MapGenerator {
+            family: name.parse().unwrap(),
+            size: 60,
+            mines: 4,
+            seed: 1,
+        };
+        let map = generator.generate();
+        let info = MapInfo::new(&map);
+        assert_eq!(info.components.len(), 1, "{}", name);
+        assert_eq!(info.mines, 4, "{}", name);
+        let mines: HashSet<SiteId> = map.mines.iter().cloned().collect();
+        assert_eq!(mines.len(), 4, "{}", name);
+        let rivers: HashSet<(SiteId, SiteId)> = map.rivers.iter().map(|r| (r.source, r.target)).collect();
+        assert_eq!(rivers.len(), map.rivers.len(), "{}", name);
+        assert!(map.rivers.iter().all(|r| r.source < r.target), "{}", name);
+        // Maps are reproducible.
+        assert_eq!(generator.generate().rivers.len(), map.rivers.len());
+    }
+}
@@ -0,0 +1,450 @@
+use punter::arena::VisGraph;
+use punter::game::{Claimed, Game};
+use punter::prelude::*;
+use punter::protocol::*;
+use punter::validate;
+use rand::{Rng, SeedableRng, StdRng};
+use serde_json;
+use std::collections::{HashMap, HashSet};
+use std::f64::consts::PI;
+use std::fmt::Write;
+use std::fs;
+use std::io::prelude::*;
+use std::path::Path;
+
+// d3.schemeCategory10, as in the visualizer.
+const PUNTER_COLORS: [&'static str; 10] = [
+    "#1f77b4",
+    "#ff7f0e",
+    "#2ca02c",
+    "#d62728",
+    "#9467bd",
+    "#8c564b",
+    "#e377c2",
+    "#7f7f7f",
+    "#bcbd22",
+    "#17becf",
+];
+const FREE_COLOR: &'static str = "#cccccc";
+const MINE_COLOR: &'static str = "#d62728";
+
+pub fn punter_color(p: PunterId) -> &'static str {
+    PUNTER_COLORS[p % PUNTER_COLORS.len()]
+}
+
+// The owners of each river, in the order of map.rivers.
+fn owners(map: &Map, game: Option<&Game>) -> Vec<Claimed> {
+    match game {
+        Some(game) => game.edges().iter().map(|e| e.claimed.clone()).collect(),
+        None => vec![Claimed::NotYet; map.rivers.len()],
+    }
+}
+
+// (punter, mine, future target) triples, from futures by punter.
+fn futures(futures: &[Vec<Future>]) -> Vec<(PunterId, SiteId, SiteId)> {
+    futures
+        .iter()
+        .enumerate()
+        .flat_map(|(p, fs)| fs.iter().map(move |f| (p, f.source, f.target)))
+        .collect()
+}
+
+fn unescape_xml(s: &str) -> String {
+    s.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&apos;", "'").replace("&amp;", "&")
+}
+
+// Graphviz DOT. Positions are kept with `pos`, so use `neato -n` to draw the map as it is. Futures, by punter, are
+// dashed lines in the punter's color.
+pub fn to_dot(map: &Map, game: Option<&Game>, futures_by_punter: &[Vec<Future>]) -> String {
+    let mines: HashSet<SiteId> = map.mines.iter().cloned().collect();
+    let mut dot = String::new();
+    writeln!(dot, "graph map {{").unwrap();
+    writeln!(dot, "  node [shape=point, width=0.05];").unwrap();
+    for site in map.sites.iter() {
+        if mines.contains(&site.id) {
+            writeln!(
+                dot,
+                "  {} [pos=\"{},{}\", shape=circle, width=0.2, style=filled, fillcolor=\"{}\", label=\"\"];",
+                site.id,
+                site.x,
+                site.y,
+                MINE_COLOR
+            ).unwrap();
+        } else {
+            writeln!(dot, "  {} [pos=\"{},{}\"];", site.id, site.x, site.y).unwrap();
+        }
+    }
+    for (river, claimed) in map.rivers.iter().zip(owners(map, game).into_iter()) {
+        let attrs = match claimed {
+            Claimed::NotYet => format!("color=\"{}\"", FREE_COLOR),
+            Claimed::Claimed(p) => format!("color=\"{}\", penwidth=3, owner={}", punter_color(p), p),
+            Claimed::Optioned(p, q) => {
+                format!(
+                    "color=\"{}:{}\", penwidth=3, owner={}, option={}",
+                    punter_color(p),
+                    punter_color(q),
+                    p,
+                    q
+                )
+            }
+        };
+        writeln!(dot, "  {} -- {} [{}];", river.source, river.target, attrs).unwrap();
+    }
+    for (p, mine, future) in futures(futures_by_punter) {
+        writeln!(
+            dot,
+            "  {} -- {} [style=dashed, color=\"{}\", constraint=false, future={}];",
+            mine,
+            future,
+            punter_color(p),
+            p
+        ).unwrap();
+    }
+    writeln!(dot, "}}").unwrap();
+    dot
+}
+
+// GraphML with x, y and mine attributes on sites, and owner, option and color attributes on rivers.
+// Sites which are targets of futures have a `future` attribute listing them as punter:mine, e.g. "0:1 2:5".
+pub fn to_graphml(map: &Map, game: Option<&Game>, futures_by_punter: &[Vec<Future>]) -> String {
+    let mines: HashSet<SiteId> = map.mines.iter().cloned().collect();
+    let mut futures_to: HashMap<SiteId, Vec<String>> = HashMap::new();
+    for (p, mine, future) in futures(futures_by_punter) {
+        futures_to.entry(future).or_insert_with(Vec::new).push(format!("{}:{}", p, mine));
+    }
+    let mut xml = String::new();
+    writeln!(xml, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>").unwrap();
+    writeln!(xml, "<graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">").unwrap();
+    for &(id, domain, ty) in [
+        ("x", "node", "double"),
+        ("y", "node", "double"),
+        ("mine", "node", "boolean"),
+        ("future", "node", "string"),
+        ("owner", "edge", "int"),
+        ("option", "edge", "int"),
+        ("color", "edge", "string"),
+    ].iter()
+    {
+        writeln!(xml, "  <key id=\"{0}\" for=\"{1}\" attr.name=\"{0}\" attr.type=\"{2}\"/>", id, domain, ty).unwrap();
+    }
+    writeln!(xml, "  <graph id=\"map\" edgedefault=\"undirected\">").unwrap();
+    for site in map.sites.iter() {
+        write!(
+            xml,
+            "    <node id=\"{}\"><data key=\"x\">{}</data><data key=\"y\">{}</data>",
+            site.id,
+            site.x,
+            site.y
+        ).unwrap();
+        if mines.contains(&site.id) {
+            write!(xml, "<data key=\"mine\">true</data>").unwrap();
+        }
+        if let Some(futures) = futures_to.get(&site.id) {
+            write!(xml, "<data key=\"future\">{}</data>", futures.join(" ")).unwrap();
+        }
+        writeln!(xml, "</node>").unwrap();
+    }
+    for (river, claimed) in map.rivers.iter().zip(owners(map, game).into_iter()) {
+        write!(xml, "    <edge source=\"{}\" target=\"{}\">", river.source, river.target).unwrap();
+        match claimed {
+            Claimed::NotYet => write!(xml, "<data key=\"color\">{}</data>", FREE_COLOR).unwrap(),
+            Claimed::Claimed(p) => {
+                write!(xml, "<data key=\"owner\">{}</data><data key=\"color\">{}</data>", p, punter_color(p)).unwrap()
+            }
+            Claimed::Optioned(p, q) => {
+                write!(
+                    xml,
+                    "<data key=\"owner\">{}</data><data key=\"option\">{}</data><data key=\"color\">{}</data>",
+                    p,
+                    q,
+                    punter_color(p)
+                ).unwrap()
+            }
+        }
+        writeln!(xml, "</edge>").unwrap();
+    }
+    writeln!(xml, "  </graph>").unwrap();
+    writeln!(xml, "</graphml>").unwrap();
+    xml
+}
+
+// Assigns site ids to the names used in a file: the names themselves if they are all numbers, otherwise
+// sequential ids in order of appearance.
+struct SiteNames {
+    names: Vec<String>,
+    index: HashMap<String, usize>,
+}
+
+impl SiteNames {
+    fn new() -> SiteNames {
+        SiteNames {
+            names: vec![],
+            index: HashMap::new(),
+        }
+    }
+
+    fn add(&mut self, name: &str) -> usize {
+        if let Some(i) = self.index.get(name) {
+            return *i;
+        }
+        self.names.push(name.to_string());
+        self.index.insert(name.to_string(), self.names.len() - 1);
+        self.names.len() - 1
+    }
+
+    fn ids(&self) -> Vec<SiteId> {
+        let numbers = self.names.iter().map(|name| name.parse::<SiteId>()).collect::<Result<Vec<_>, _>>();
+        match numbers {
+            Ok(numbers) => numbers,
+            Err(_) => (0..self.names.len() as SiteId).collect(),
+        }
+    }
+}
+
+// Builds a map, placing sites without a position on a circle.
+fn build_map(names: SiteNames, positions: HashMap<usize, (f64, f64)>, rivers: Vec<(usize, usize)>, mines: Vec<usize>) -> Map {
+    let ids = names.ids();
+    let n = ids.len();
+    Map {
+        sites: ids.iter()
+            .enumerate()
+            .map(|(i, id)| {
+                let (x, y) = positions.get(&i).cloned().unwrap_or_else(|| {
+                    let angle = 2.0 * PI * i as f64 / n as f64;
+                    (angle.cos() * 100.0, angle.sin() * 100.0)
+                });
+                Site { id: *id, x, y }
+            })
+            .collect(),
+        rivers: rivers
+            .into_iter()
+            .map(|(s, t)| {
+                River {
+                    source: ids[s],
+                    target: ids[t],
+                }
+            })
+            .collect(),
+        mines: mines.into_iter().map(|m| ids[m]).collect(),
+    }
+}
+
+// Attributes of a start tag, e.g. `<node id="n0">`.
+fn attributes(tag: &str) -> HashMap<String, String> {
+    let mut attrs = HashMap::new();
+    let mut rest = tag;
+    while let Some(eq) = rest.find('=') {
+        let name = rest[..eq].split_whitespace().last().unwrap_or("").to_string();
+        let value = rest[eq + 1..].trim_left();
+        let quote = match value.chars().next() {
+            Some(c) if c == '"' || c == '\'' => c,
+            _ => break,
+        };
+        let end = match value[1..].find(quote) {
+            Some(end) => end + 1,
+            None => break,
+        };
+        attrs.insert(name, unescape_xml(&value[1..end]));
+        rest = &value[end + 1..];
+    }
+    attrs
+}
+
+// Reads nodes, edges and their x, y and mine attributes. This is not a full XML parser, but it reads the
+// GraphML which graph tools write.
+pub fn from_graphml(xml: &str) -> PunterResult<Map> {
+    let mut key_names = HashMap::new(); // key id -> attr.name
+    let mut names = SiteNames::new();
+    let mut positions: HashMap<usize, (f64, f64)> = HashMap::new();
+    let mut rivers = vec![];
+    let mut mines = vec![];
+
+    let mut node: Option<usize> = None;
+    let mut data_key: Option<String> = None;
+    let mut rest = xml;
+    while let Some(start) = rest.find('<') {
+        let text = &rest[..start];
+        rest = &rest[start..];
+        if let (Some(node), Some(key)) = (node, data_key.take()) {
+            let value = unescape_xml(text.trim());
+            match key_names.get(&key).map(|name: &String| name.as_str()).unwrap_or(key.as_str()) {
+                "x" => positions.entry(node).or_insert((0.0, 0.0)).0 = value.parse().unwrap_or(0.0),
+                "y" => positions.entry(node).or_insert((0.0, 0.0)).1 = value.parse().unwrap_or(0.0),
+                "mine" if value == "true" || value == "1" => mines.push(node),
+                _ => {}
+            }
+        }
+        let end = if rest.starts_with("<!--") {
+            rest.find("-->").map(|end| end + 3)
+        } else {
+            rest.find('>').map(|end| end + 1)
+        };
+        let end = end.ok_or_else(|| PunterError::InvalidArgument("unterminated tag".to_string()))?;
+        let tag = &rest[1..end - 1];
+        rest = &rest[end..];
+
+        let name = tag.split_whitespace().next().unwrap_or("");
+        let attrs = attributes(tag);
+        match name {
+            "key" => {
+                if let (Some(id), Some(name)) = (attrs.get("id"), attrs.get("attr.name")) {
+                    key_names.insert(id.clone(), name.clone());
+                }
+            }
+            "node" => {
+                let id = attrs.get("id").ok_or_else(|| PunterError::InvalidArgument("node without id".to_string()))?;
+                let i = names.add(id);
+                node = if tag.ends_with('/') { None } else { Some(i) };
+            }
+            "/node" => node = None,
+            "edge" => {
+                match (attrs.get("source"), attrs.get("target")) {
+                    (Some(s), Some(t)) => {
+                        let s = names.add(s);
+                        let t = names.add(t);
+                        rivers.push((s, t));
+                    }
+                    _ => return Err(PunterError::InvalidArgument("edge without source or target".to_string())),
+                }
+            }
+            "data" => data_key = attrs.get("key").cloned(),
+            _ => {}
+        }
+    }
+    Ok(build_map(names, positions, rivers, mines))
+}
+
+// One river per line, as two site names separated by whitespace or a comma. Lines starting with '#' or
+// '%' are comments.
+pub fn from_edge_list(s: &str) -> PunterResult<Map> {
+    let mut names = SiteNames::new();
+    let mut rivers = vec![];
+    for (i, line) in s.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with('%') {
+            continue;
+        }
+        let fields = line.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|f| !f.is_empty())
+            .collect::<Vec<_>>();
+        if fields.len() < 2 {
+            return Err(PunterError::InvalidArgument(format!("line {}: expected two sites", i + 1)));
+        }
+        let s = names.add(fields[0]);
+        let t = names.add(fields[1]);
+        rivers.push((s, t));
+    }
+    Ok(build_map(names, HashMap::new(), rivers, vec![]))
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum GraphFormat {
+    Dot,
+    GraphML,
+    EdgeList,
+}
+
+impl GraphFormat {
+    // Guesses the format from a file extension.
+    pub fn from_path(path: &Path) -> GraphFormat {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("dot") | Some("gv") => GraphFormat::Dot,
+            Some("graphml") | Some("xml") => GraphFormat::GraphML,
+            _ => GraphFormat::EdgeList,
+        }
+    }
+}
+
+// Exports a map, or a recorded battle after `turns` moves (all moves by default), to DOT or GraphML.
+pub fn export_run<P: AsRef<Path>>(input: P, format: GraphFormat, turns: Option<usize>) -> PunterResult<String> {
+    let mut s = String::new();
+    fs::File::open(input)?.read_to_string(&mut s)?;
+    let value: serde_json::Value = serde_json::from_str(&s)?;
+    let (map, game, futures) = if value.get("moves").is_some() {
+        let vis_graph: VisGraph = serde_json::from_value(value)?;
+        let game = vis_graph.replay(turns.unwrap_or(vis_graph.moves.len()));
+        (vis_graph.map, Some(game), vis_graph.futures)
+    } else {
+        (serde_json::from_value(value)?, None, vec![])
+    };
+    match format {
+        GraphFormat::Dot => Ok(to_dot(&map, game.as_ref(), &futures)),
+        GraphFormat::GraphML => Ok(to_graphml(&map, game.as_ref(), &futures)),
+        GraphFormat::EdgeList => Err(PunterError::InvalidArgument("export to edge lists is not supported".to_string())),
+    }
+}
+
+// Imports a GraphML or edge list file as a normalized map. Mines are `mines` if given, those marked in the
+// file otherwise, or else `random_mines` sites chosen with `seed`.
+pub fn import_run<P: AsRef<Path>>(input: P, mines: Vec<SiteId>, random_mines: usize, seed: u64) -> PunterResult<Map> {
+    let format = GraphFormat::from_path(input.as_ref());
+    let mut s = String::new();
+    fs::File::open(input)?.read_to_string(&mut s)?;
+    let mut map = match format {
+        GraphFormat::GraphML => from_graphml(&s)?,
+        GraphFormat::EdgeList => from_edge_list(&s)?,
+        GraphFormat::Dot => return Err(PunterError::InvalidArgument("import from DOT is not supported".to_string())),
+    };
+    if !mines.is_empty() {
+        map.mines = mines;
+    } else if map.mines.is_empty() {
+        let mut ids = map.sites.iter().map(|site| site.id).collect::<Vec<_>>();
+        StdRng::from_seed(&[seed as usize][..]).shuffle(&mut ids);
+        map.mines = ids.into_iter().take(random_mines).collect();
+    }
+    for problem in validate::validate(&map) {
+        warn!("import: {}", problem);
+    }
+    Ok(validate::normalize(&map))
+}
+
+#[test]
+fn graphml_round_trip_test() {
+    use punter::arena;
+    use punter::game::EdgeClaim;
+
+    let map = arena::read_map(&arena::builtin_map_path("sample.json")).unwrap();
+    let mut game = arena::sample_game("sample.json");
+    game.apply_edge_claim(EdgeClaim::new(1, 5, 6), false);
+
+    let xml = to_graphml(&map, Some(&game), &[]);
+    assert!(xml.contains("<edge source=\"6\" target=\"5\"><data key=\"owner\">1</data>"));
+    let imported = from_graphml(&xml).unwrap();
+    assert_eq!(imported.mines, map.mines);
+    assert_eq!(imported.rivers.len(), map.rivers.len());
+    assert_eq!(imported.sites.iter().map(|s| (s.id, s.x, s.y)).collect::<Vec<_>>(),
+               map.sites.iter().map(|s| (s.id, s.x, s.y)).collect::<Vec<_>>());
+
+    let dot = to_dot(&map, Some(&game), &[]);
+    assert!(dot.contains(&format!("6 -- 5 [color=\"{}\", penwidth=3, owner=1];", punter_color(1))));
+
+    let edges = from_edge_list("# a triangle\na b\nb,c\nc a\n").unwrap();
+    assert_eq!(edges.sites.len(), 3);
+    assert_eq!(edges.rivers.iter().map(|r| (r.source, r.target)).collect::<Vec<_>>(), vec![(0, 1), (1, 2), (2, 0)]);
+}
+
+#[test]
+fn export_futures_test() {
+    use punter::arena;
+    use std::env;
+
+    let vis_graph = VisGraph {
+        map: arena::read_map(&arena::builtin_map_path("sample.json")).unwrap(),
+        moves: vec![Move::from(Claim { punter: 1, source: 5, target: 6 })],
+        punters: vec!["a".to_string(), "b".to_string()],
+        settings: "futures".parse().unwrap(),
+        futures: vec![vec![Future { source: 1, target: 3 }], vec![Future { source: 5, target: 3 }]],
+        turns: vec![],
+        events: vec![],
+    };
+    let path = env::temp_dir().join("icfp2017-export-futures-test.json");
+    serde_json::to_writer(fs::File::create(&path).unwrap(), &vis_graph).unwrap();
+    let dot = export_run(&path, GraphFormat::Dot, None).unwrap();
+    let xml = export_run(&path, GraphFormat::GraphML, None).unwrap();
+    fs::remove_file(&path).ok();
+
+    assert!(dot.contains(&format!("1 -- 3 [style=dashed, color=\"{}\", constraint=false, future=0];", punter_color(0))));
+    assert!(dot.contains(&format!("5 -- 3 [style=dashed, color=\"{}\", constraint=false, future=1];", punter_color(1))));
+    assert!(xml.contains("<data key=\"future\">0:1 1:5</data>"));
+    assert_eq!(from_graphml(&xml).unwrap().rivers.len(), vis_graph.map.rivers.len());
+}
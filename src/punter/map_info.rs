@@ -0,0 +1,319 @@
+use punter::prelude::*;
+use punter::protocol::*;
+use serde_json;
+use std::cmp;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+
+const UNVISITED: usize = ::std::usize::MAX;
+
+// A summary of a map's structure, to help choosing maps for tournaments.
+#[derive(Debug, Serialize)]
+pub struct MapInfo {
+    pub sites: usize,
+    pub rivers: usize,
+    pub mines: usize,
+    pub degree_distribution: BTreeMap<usize, usize>, // degree -> number of sites
+    pub components: Vec<Component>,                   // largest first
+    pub diameter: usize,                              // the longest shortest path within a component
+    pub mine_distances: Vec<Vec<Option<usize>>>,      // in the order of map.mines. None if unreachable
+    pub bridges: Vec<(SiteId, SiteId)>,
+    pub articulation_points: Vec<SiteId>,
+    pub max_scores: Vec<MineScore>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Component {
+    pub sites: usize,
+    pub rivers: usize,
+    pub mines: Vec<SiteId>,
+}
+
+// The score a punter gets from a mine by claiming every river.
+#[derive(Debug, Serialize)]
+pub struct MineScore {
+    pub mine: SiteId,
+    pub max_score: u64,
+}
+
+// Adjacency lists over site indices. Rivers whose sites are not in the map are ignored.
+struct Graph {
+    site_ids: Vec<SiteId>,
+    adj: Vec<Vec<(usize, usize)>>, // node -> (node, river index)
+    rivers: Vec<(usize, usize)>,
+    mines: Vec<usize>,
+}
+
+impl Graph {
+    fn new(map: &Map) -> Graph {
+        let site_ids: Vec<SiteId> = map.sites.iter().map(|site| site.id).collect();
+        let index: HashMap<SiteId, usize> = site_ids.iter().enumerate().map(|(i, id)| (*id, i)).collect();
+        let rivers: Vec<(usize, usize)> = map.rivers
+            .iter()
+            .filter_map(|r| match (index.get(&r.source), index.get(&r.target)) {
+                (Some(s), Some(t)) => Some((*s, *t)),
+                _ => None,
+            })
+            .collect();
+        let mut adj = vec![vec![]; site_ids.len()];
+        for (i, &(s, t)) in rivers.iter().enumerate() {
+            adj[s].push((t, i));
+            adj[t].push((s, i));
+        }
+        Graph {
+            mines: map.mines.iter().filter_map(|m| index.get(m).cloned()).collect(),
+            site_ids,
+            adj,
+            rivers,
+        }
+    }
+
+    fn bfs(&self, source: usize) -> Vec<usize> {
+        let mut dist = vec![UNVISITED; self.adj.len()];
+        dist[source] = 0;
+        let mut q = VecDeque::new();
+        q.push_back(source);
+        while let Some(v) = q.pop_front() {
+            for &(w, _) in self.adj[v].iter() {
+                if dist[w] == UNVISITED {
+                    dist[w] = dist[v] + 1;
+                    q.push_back(w);
+                }
+            }
+        }
+        dist
+    }
+
+    // Component index of each node.
+    fn components(&self) -> (usize, Vec<usize>) {
+        let mut component = vec![UNVISITED; self.adj.len()];
+        let mut n = 0;
+        for root in 0..self.adj.len() {
+            if component[root] != UNVISITED {
+                continue;
+            }
+            component[root] = n;
+            let mut stack = vec![root];
+            while let Some(v) = stack.pop() {
+                for &(w, _) in self.adj[v].iter() {
+                    if component[w] == UNVISITED {
+                        component[w] = n;
+                        stack.push(w);
+                    }
+                }
+            }
+            n += 1;
+        }
+        (n, component)
+    }
+
+    // Tarjan's algorithm, iteratively so that large maps do not overflow the stack.
+    // Returns bridges (river indices) and whether each node is an articulation point.
+    fn cut_rivers_and_sites(&self) -> (Vec<usize>, Vec<bool>) {
+        let n = self.adj.len();
+        let mut disc = vec![UNVISITED; n];
+        let mut low = vec![0; n];
+        let mut is_articulation = vec![false; n];
+        let mut bridges = vec![];
+        let mut time = 0;
+        for root in 0..n {
+            if disc[root] != UNVISITED {
+                continue;
+            }
+            disc[root] = time;
+            low[root] = time;
+            time += 1;
+            let mut root_children = 0;
+            let mut stack: Vec<(usize, Option<usize>, usize)> = vec![(root, None, 0)]; // (node, parent river, next adj)
+            while !stack.is_empty() {
+                let top = stack.len() - 1;
+                let (v, parent_river, pos) = stack[top];
+                if pos < self.adj[v].len() {
+                    stack[top].2 += 1;
+                    let (w, river) = self.adj[v][pos];
+                    if Some(river) == parent_river {
+                        continue;
+                    }
+                    if disc[w] == UNVISITED {
+                        disc[w] = time;
+                        low[w] = time;
+                        time += 1;
+                        stack.push((w, Some(river), 0));
+                    } else {
+                        low[v] = cmp::min(low[v], disc[w]);
+                    }
+                } else {
+                    stack.pop();
+                    if let Some(&(u, _, _)) = stack.last() {
+                        low[u] = cmp::min(low[u], low[v]);
+                        if low[v] > disc[u] {
+                            bridges.push(parent_river.unwrap());
+                        }
+                        if u == root {
+                            root_children += 1;
+                        } else if low[v] >= disc[u] {
+                            is_articulation[u] = true;
+                        }
+                    }
+                }
+            }
+            is_articulation[root] = root_children > 1;
+        }
+        (bridges, is_articulation)
+    }
+}
+
+impl MapInfo {
+    pub fn new(map: &Map) -> MapInfo {
+        let graph = Graph::new(map);
+        let n = graph.adj.len();
+
+        let mut degree_distribution = BTreeMap::new();
+        for adj in graph.adj.iter() {
+            *degree_distribution.entry(adj.len()).or_insert(0) += 1;
+        }
+
+        let (component_count, component) = graph.components();
+        let mut components: Vec<Component> = (0..component_count)
+            .map(|_| {
+                Component {
+                    sites: 0,
+                    rivers: 0,
+                    mines: vec![],
+                }
+            })
+            .collect();
+        for v in 0..n {
+            components[component[v]].sites += 1;
+        }
+        for &(s, _) in graph.rivers.iter() {
+            components[component[s]].rivers += 1;
+        }
+        for &mine in graph.mines.iter() {
+            components[component[mine]].mines.push(graph.site_ids[mine]);
+        }
+        components.sort_by(|a, b| b.sites.cmp(&a.sites));
+
+        let diameter = (0..n)
+            .map(|v| graph.bfs(v).into_iter().filter(|d| *d != UNVISITED).max().unwrap_or(0))
+            .max()
+            .unwrap_or(0);
+
+        let dist_from_mine: Vec<Vec<usize>> = graph.mines.iter().map(|mine| graph.bfs(*mine)).collect();
+        let mine_distances = dist_from_mine
+            .iter()
+            .map(|dist| {
+                graph.mines
+                    .iter()
+                    .map(|m| if dist[*m] == UNVISITED { None } else { Some(dist[*m]) })
+                    .collect()
+            })
+            .collect();
+        let max_scores = graph.mines
+            .iter()
+            .zip(dist_from_mine.iter())
+            .map(|(mine, dist)| {
+                MineScore {
+                    mine: graph.site_ids[*mine],
+                    max_score: dist.iter().filter(|d| **d != UNVISITED).map(|d| (d * d) as u64).sum(),
+                }
+            })
+            .collect();
+
+        let (bridges, is_articulation) = graph.cut_rivers_and_sites();
+        let mut bridges: Vec<(SiteId, SiteId)> = bridges
+            .into_iter()
+            .map(|river| {
+                let (s, t) = graph.rivers[river];
+                let (s, t) = (graph.site_ids[s], graph.site_ids[t]);
+                (cmp::min(s, t), cmp::max(s, t))
+            })
+            .collect();
+        bridges.sort();
+        let mut articulation_points: Vec<SiteId> = (0..n).filter(|v| is_articulation[*v]).map(|v| graph.site_ids[v]).collect();
+        articulation_points.sort();
+
+        MapInfo {
+            sites: map.sites.len(),
+            rivers: map.rivers.len(),
+            mines: map.mines.len(),
+            degree_distribution,
+            components,
+            diameter,
+            mine_distances,
+            bridges,
+            articulation_points,
+            max_scores,
+        }
+    }
+
+    pub fn print(&self) {
+        println!("sites: {}, rivers: {}, mines: {}", self.sites, self.rivers, self.mines);
+        println!("degree distribution:");
+        for (degree, count) in self.degree_distribution.iter() {
+            println!("  {:>3}: {}", degree, count);
+        }
+        println!("components: {}", self.components.len());
+        for (i, c) in self.components.iter().enumerate().filter(|&(_, c)| !c.mines.is_empty()) {
+            println!("  #{}: {} sites, {} rivers, mines {:?}", i, c.sites, c.rivers, c.mines);
+        }
+        let mineless = self.components.iter().filter(|c| c.mines.is_empty()).count();
+        if mineless > 0 {
+            println!("  ({} components without mines)", mineless);
+        }
+        println!("diameter: {}", self.diameter);
+        println!("mine distances:");
+        for row in self.mine_distances.iter() {
+            let row = row.iter()
+                .map(|d| d.map_or("-".to_string(), |d| d.to_string()))
+                .map(|d| format!("{:>4}", d))
+                .collect::<Vec<_>>();
+            println!("  {}", row.join(""));
+        }
+        println!("bridges: {}", self.bridges.len());
+        println!("articulation points: {}", self.articulation_points.len());
+        println!("max score per mine:");
+        for s in self.max_scores.iter() {
+            println!("  {:>6}: {}", s.mine, s.max_score);
+        }
+    }
+}
+
+pub fn map_info_run<P: AsRef<Path>>(path: P, json: bool) -> PunterResult<()> {
+    let map: Map = serde_json::from_reader(fs::File::open(path)?)?;
+    let info = MapInfo::new(&map);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&info)?);
+    } else {
+        info.print();
+    }
+    Ok(())
+}
+
+#[test]
+fn map_info_test() {
+    use punter::arena;
+
+    // sample.json: a ring 0..7 with chords 1-3, 3-5, 5-7 and 7-1. Mines are 1 and 5.
+    let info = MapInfo::new(&arena::read_map(&arena::builtin_map_path("sample.json")).unwrap());
+    assert_eq!((info.sites, info.rivers, info.mines), (8, 12, 2));
+    assert_eq!(info.components.len(), 1);
+    assert_eq!(info.components[0].mines, vec![1, 5]);
+    assert_eq!(info.mine_distances, vec![vec![Some(0), Some(2)], vec![Some(2), Some(0)]]);
+    assert!(info.bridges.is_empty());
+    assert!(info.articulation_points.is_empty());
+
+    let map = Map {
+        sites: (0..4).map(|id| Site { id, x: 0.0, y: 0.0 }).collect(),
+        rivers: vec![River { source: 0, target: 1 }, River { source: 1, target: 2 }],
+        mines: vec![0, 3],
+    };
+    let info = MapInfo::new(&map);
+    assert_eq!(info.components.len(), 2);
+    assert_eq!(info.diameter, 2);
+    assert_eq!(info.mine_distances[0][1], None);
+    assert_eq!(info.bridges, vec![(0, 1), (1, 2)]);
+    assert_eq!(info.articulation_points, vec![1]);
+    assert_eq!(info.max_scores[0].max_score, 1 + 4);
+}
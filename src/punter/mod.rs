@@ -1,9 +1,28 @@
 mod bot;
+mod budget;
 mod game;
 mod io;
+mod ponder;
 mod protocol;
 mod prelude;
+mod search;
+mod watchdog;
 pub mod arena;
+pub mod arena_config;
+pub mod compare;
+pub mod export;
+pub mod gen_map;
+pub mod graph_io;
+pub mod map_info;
+pub mod opponent;
+pub mod parallel;
+pub mod params;
 pub mod play;
+pub mod rating;
+pub mod serve;
+pub mod svg;
+pub mod tui;
+pub mod tune;
+pub mod validate;
 
 pub use punter::game::Game;
@@ -0,0 +1,163 @@
+use punter::game::{EdgeIndex, Game, Node};
+use punter::parallel;
+use punter::prelude::*;
+use std::collections::HashSet;
+
+// Claims which count as "recent" when estimating where an opponent is heading.
+const RECENT_CLAIMS: usize = 3;
+// Rivers touching recently claimed sites are this much more likely to be claimed next.
+const RECENCY_BOOST: f64 = 2.0;
+// Probabilities are proportional to weight^SHARPNESS.
+const SHARPNESS: f64 = 2.0;
+
+// What we know about an opponent from their claims so far.
+#[derive(Debug, Clone)]
+pub struct OpponentView {
+    pub punter: PunterId,
+    // Mines the opponent is building networks from.
+    pub mines: Vec<Node>,
+    // Sites touched by the opponent's most recent claims, newest first. This is where they are expanding.
+    pub frontier: Vec<Node>,
+    // Rivers the opponent is likely to claim next, with probabilities, most likely first.
+    pub targets: Vec<(EdgeIndex, f64)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct OpponentModel {
+    opponents: Vec<Option<OpponentView>>, // punter -> view, None for us
+}
+
+impl OpponentView {
+    fn new(game: &Game, p: PunterId) -> OpponentView {
+        let connectivity = game.connectivity(p);
+        let mines = connectivity.active_mines(game);
+
+        let mut frontier = vec![];
+        for &(_, index) in game.history().iter().rev().filter(|&&(q, _)| q == p).take(RECENT_CLAIMS) {
+            let e = &game.edges()[index];
+            for node in [e.target, e.source].iter() {
+                if !frontier.contains(node) {
+                    frontier.push(*node);
+                }
+            }
+        }
+        let recent: HashSet<Node> = frontier.iter().cloned().collect();
+
+        // The opponent is assumed to play like EdgeWeight, preferring to continue where they left off.
+        let mut targets = game.calc_edge_weights(p)
+            .into_iter()
+            .zip(game.edges().iter())
+            .enumerate()
+            .filter(|&(_, (weight, e))| {
+                weight > 0.0 && e.is_empty() &&
+                    (connectivity.is_connected_to_mine(e.source) || connectivity.is_connected_to_mine(e.target))
+            })
+            .map(|(index, (weight, e))| {
+                let boost = if recent.contains(&e.source) || recent.contains(&e.target) {
+                    RECENCY_BOOST
+                } else {
+                    1.0
+                };
+                (index, (weight * boost).powf(SHARPNESS))
+            })
+            .collect::<Vec<_>>();
+        let total: f64 = targets.iter().map(|&(_, w)| w).sum();
+        for target in targets.iter_mut() {
+            target.1 /= total;
+        }
+        targets.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap().then(a.0.cmp(&b.0)));
+
+        OpponentView {
+            punter: p,
+            mines,
+            frontier,
+            targets,
+        }
+    }
+
+    pub fn next_claim_probability(&self, edge: EdgeIndex) -> f64 {
+        self.targets
+            .iter()
+            .find(|&&(index, _)| index == edge)
+            .map_or(0.0, |&(_, p)| p)
+    }
+}
+
+impl OpponentModel {
+    pub fn new(game: &Game) -> OpponentModel {
+        OpponentModel {
+            opponents: parallel::map((0..game.punters()).collect(), |p| if p == game.me {
+                None
+            } else {
+                Some(OpponentView::new(game, p))
+            }),
+        }
+    }
+
+    pub fn view(&self, p: PunterId) -> Option<&OpponentView> {
+        self.opponents[p].as_ref()
+    }
+
+    // Probability that opponent p claims the river within their next `turns` turns.
+    pub fn probability(&self, p: PunterId, edge: EdgeIndex, turns: usize) -> f64 {
+        match self.view(p) {
+            Some(view) => 1.0 - (1.0 - view.next_claim_probability(edge)).powi(turns as i32),
+            None => 0.0,
+        }
+    }
+
+    // Probability that any opponent claims the river before our next turn.
+    pub fn contention(&self, edge: EdgeIndex) -> f64 {
+        1.0 -
+            self.opponents
+                .iter()
+                .filter_map(|view| view.as_ref())
+                .map(|view| 1.0 - view.next_claim_probability(edge))
+                .product::<f64>()
+    }
+}
+
+// The river opponent p is most likely to claim next: their most likely target, or the free river with the
+// highest EdgeWeight if they have no network yet.
+pub fn predict_claim(game: &Game, p: PunterId) -> Option<EdgeIndex> {
+    if let Some(&(index, _)) = OpponentView::new(game, p).targets.first() {
+        return Some(index);
+    }
+    game.calc_edge_weights(p)
+        .into_iter()
+        .enumerate()
+        .filter(|&(index, _)| game.edges()[index].is_empty())
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap().then(b.0.cmp(&a.0)))
+        .map(|(index, _)| index)
+}
+
+#[test]
+fn opponent_model_test() {
+    use punter::arena;
+    use punter::game::EdgeClaim;
+
+    // sample.json: mines are sites 1 and 5.
+    let mut game = arena::sample_game("sample.json");
+    game.apply_edge_claim(EdgeClaim::new(1, 5, 6), false);
+    game.apply_edge_claim(EdgeClaim::new(0, 1, 2), false);
+    game.apply_edge_claim(EdgeClaim::new(1, 6, 7), false);
+
+    let model = OpponentModel::new(&game);
+    assert!(model.view(0).is_none());
+    let view = model.view(1).unwrap();
+    assert_eq!(view.mines, vec![5]);
+    assert_eq!(view.frontier, vec![7, 6, 5]);
+    let total: f64 = view.targets.iter().map(|&(_, p)| p).sum();
+    assert!((total - 1.0).abs() < 1e-9);
+    for &(index, _) in view.targets.iter() {
+        let e = &game.edges()[index];
+        // Either extends the network or starts from the other mine.
+        assert!([1, 5, 6, 7].iter().any(|n| *n == e.source || *n == e.target));
+    }
+    // Rivers touching the opponent's network are contested, others are safe.
+    // River 6 is (3, 4).
+    let (contested, safe) = (view.targets[0].0, 6);
+    assert!(model.contention(contested) > 0.0);
+    assert_eq!(model.contention(safe), 0.0);
+    assert!(model.probability(1, contested, 2) > model.probability(1, contested, 1));
+}
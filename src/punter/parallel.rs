@@ -0,0 +1,46 @@
+use punter::prelude::*;
+use rayon;
+use rayon::prelude::*;
+use std::sync::atomic::{ATOMIC_USIZE_INIT, AtomicUsize, Ordering};
+
+// Number of threads a single move computation may use. 0 (unset) and 1 mean sequential, which is what the
+// arena wants since it already plays many games at once on rayon's pool.
+static THREADS: AtomicUsize = ATOMIC_USIZE_INIT;
+
+// Lets move computations use `threads` threads. Call this once, before any parallel work starts.
+pub fn set_threads(threads: usize) -> PunterResult<()> {
+    if threads > 1 {
+        rayon::initialize(rayon::Configuration::new().num_threads(threads))
+            .map_err(|e| PunterError::InvalidArgument(format!("failed to start {} threads: {}", threads, e)))?;
+    }
+    THREADS.store(threads, Ordering::SeqCst);
+    Ok(())
+}
+
+pub fn is_enabled() -> bool {
+    THREADS.load(Ordering::Relaxed) > 1
+}
+
+// Maps `f` over `items`, in parallel if enabled. The order of results is that of `items`.
+pub fn map<T, R, F>(items: Vec<T>, f: F) -> Vec<R>
+where
+    T: Send,
+    R: Send,
+    F: Fn(T) -> R + Sync + Send,
+{
+    if is_enabled() && items.len() > 1 {
+        items.into_par_iter().map(f).collect()
+    } else {
+        items.into_iter().map(f).collect()
+    }
+}
+
+#[test]
+fn send_sync_test() {
+    use punter::game::Game;
+    use punter::opponent::OpponentModel;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Game>();
+    assert_send_sync::<OpponentModel>();
+}
@@ -0,0 +1,118 @@
+use punter::prelude::*;
+use serde_json;
+use std::fs;
+use std::io::prelude::*;
+use std::path::Path;
+
+// Scoring terms of the EdgeWeight heuristic. The default is the original heuristic: each reachable site
+// promises dist^2 points and nothing else is taken into account.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EdgeWeightParams {
+    // A site at distance d from a mine promises d^distance_exponent points.
+    pub distance_exponent: f64,
+    // Weights are multiplied by exp(-contention_penalty * n), where n is the number of opponents' rivers
+    // touching the river's sites.
+    pub contention_penalty: f64,
+    // Other mines promise (1 + mine_link_bonus) times as much as ordinary sites.
+    pub mine_link_bonus: f64,
+    // A future's target promises an additional futures_bonus * d^3 points.
+    pub futures_bonus: f64,
+    // Weights are multiplied by (1 + contested_bonus * p), where p is the probability that an opponent
+    // claims the river before our next turn. See OpponentModel.
+    pub contested_bonus: f64,
+}
+
+impl Default for EdgeWeightParams {
+    fn default() -> Self {
+        EdgeWeightParams {
+            distance_exponent: 2.0,
+            contention_penalty: 0.0,
+            mine_link_bonus: 0.0,
+            futures_bonus: 0.0,
+            contested_bonus: 0.0,
+        }
+    }
+}
+
+impl EdgeWeightParams {
+    pub fn from_file<P: AsRef<Path>>(path: P) -> PunterResult<Self> {
+        let mut f = fs::File::open(path)?;
+        let mut s = String::new();
+        f.read_to_string(&mut s)?;
+        Ok(serde_json::from_str(&s)?)
+    }
+
+    pub fn names() -> &'static [&'static str] {
+        &[
+            "distance_exponent",
+            "contention_penalty",
+            "mine_link_bonus",
+            "futures_bonus",
+            "contested_bonus",
+        ]
+    }
+
+    // The parameters as a vector, in the order of names().
+    pub fn to_vec(&self) -> Vec<f64> {
+        vec![
+            self.distance_exponent,
+            self.contention_penalty,
+            self.mine_link_bonus,
+            self.futures_bonus,
+            self.contested_bonus,
+        ]
+    }
+
+    pub fn from_vec(v: &[f64]) -> Self {
+        assert_eq!(v.len(), Self::names().len());
+        EdgeWeightParams {
+            distance_exponent: v[0],
+            contention_penalty: v[1],
+            mine_link_bonus: v[2],
+            futures_bonus: v[3],
+            contested_bonus: v[4],
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: f64) -> PunterResult<()> {
+        match name {
+            "distance_exponent" => self.distance_exponent = value,
+            "contention_penalty" => self.contention_penalty = value,
+            "mine_link_bonus" => self.mine_link_bonus = value,
+            "futures_bonus" => self.futures_bonus = value,
+            "contested_bonus" => self.contested_bonus = value,
+            _ => return Err(PunterError::InvalidArgument(format!("unknown parameter: {}", name))),
+        }
+        Ok(())
+    }
+
+    // Applies an override in "name=value" form.
+    pub fn set_from_str(&mut self, s: &str) -> PunterResult<()> {
+        let mut kv = s.splitn(2, '=');
+        match (kv.next(), kv.next()) {
+            (Some(name), Some(value)) => {
+                let value = value.trim().parse::<f64>().map_err(|_| {
+                    PunterError::InvalidArgument(format!("not a number: {}", s))
+                })?;
+                self.set(name.trim(), value)
+            }
+            _ => Err(PunterError::InvalidArgument(format!("expected name=value: {}", s))),
+        }
+    }
+}
+
+#[test]
+fn set_from_str_test() {
+    let mut params = EdgeWeightParams::default();
+    params.set_from_str("contention_penalty=0.5").unwrap();
+    params.set_from_str(" futures_bonus = 2").unwrap();
+    assert_eq!(params.contention_penalty, 0.5);
+    assert_eq!(params.futures_bonus, 2.0);
+    assert!(params.set_from_str("no_such_param=1").is_err());
+    assert!(params.set_from_str("distance_exponent").is_err());
+
+    let params: EdgeWeightParams = serde_json::from_str(r#"{"mine_link_bonus": 1.5}"#).unwrap();
+    assert_eq!(params.distance_exponent, 2.0);
+    assert_eq!(params.mine_link_bonus, 1.5);
+}
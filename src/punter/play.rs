@@ -1,12 +1,26 @@
 use punter::game::{Game, Strategy};
 use punter::io::*;
+use punter::params::EdgeWeightParams;
+use punter::ponder::Ponder;
 use punter::prelude::*;
 use punter::protocol::*;
+use punter::validate;
+use punter::watchdog;
 use serde_json;
 use std::io::{stdin, stdout};
+use std::time::Instant;
 
-pub fn offline_run() -> PunterResult<()> {
-    let strategy = Strategy::EdgeWeight;
+// Rejects maps which Game can not handle, such as ones with self-loops, before they make it panic.
+fn setup_game(setup: SetupSP, params: EdgeWeightParams) -> PunterResult<Game> {
+    validate::check(&setup.map)?;
+    let mut game: Game = setup.into();
+    game.params = params;
+    Ok(game)
+}
+
+pub fn offline_run(params: EdgeWeightParams) -> PunterResult<()> {
+    let start = Instant::now();
+    let strategy = Strategy::Endgame;
     let mut io = OfflineIO::new(stdin(), stdout());
 
     // 1. handshake
@@ -28,7 +42,7 @@ pub fn offline_run() -> PunterResult<()> {
         // setup
         let setup: SetupSP = serde_json::from_str(&s)?;
         debug!("Setup: {:?}", setup);
-        let game: Game = setup.into();
+        let game = setup_game(setup, params)?;
         let setup = OfflineSetupPS {
             ready: game.me,
             // TODO: Support futures
@@ -43,14 +57,23 @@ pub fn offline_run() -> PunterResult<()> {
         // game play
         let game_play: OfflineGamePlaySP = serde_json::from_str(&s)?;
         debug!("OfflineGamePlaySP: {:?}", game_play);
+        let decode_start = Instant::now();
         let mut game: Game = game_play.into();
-        let mov = game.play(strategy);
+        // Encoding the state takes about as long as decoding it.
+        game.time_control.record_overhead(decode_start.elapsed());
+        let budget = game.time_control.budget(start);
+        let mov = watchdog::play(&game, strategy, &budget);
         game.apply_move(mov.clone());
+        game.time_control.on_move_sent(&mov);
         let game_play = mov.into_offline_game_play_ps(serde_json::Value::String(game.encode()));
         debug!("OfflineGamePlayPS: {:?}", game_play);
         io.write_json_message(
             &serde_json::to_string(&game_play).unwrap(),
         )?;
+    } else if let Some(_) = o.get("timeout") {
+        let timeout: TimeoutSP = serde_json::from_str(&s)?;
+        // There is no state to update here. The next gameplay message tells us that our move was lost.
+        warn!("TimeoutSP: {:?}", timeout);
     } else if let Some(_) = o.get("stop") {
         let scoring: OfflineScoringSP = serde_json::from_str(&s)?;
         debug!("OfflineScoringSP: {:?}", scoring);
@@ -69,8 +92,8 @@ pub fn offline_run() -> PunterResult<()> {
     Ok(())
 }
 
-pub fn online_run(address: &str) -> PunterResult<Game> {
-    let strategy = Strategy::EdgeWeight;
+pub fn online_run(address: &str, params: EdgeWeightParams) -> PunterResult<Game> {
+    let strategy = Strategy::Endgame;
     let mut io = OnlineIO::new(address);
 
     // 1. handshake
@@ -86,8 +109,7 @@ pub fn online_run(address: &str) -> PunterResult<Game> {
     let s = io.read_json_message()?;
     let setup: SetupSP = serde_json::from_str(&s)?;
     debug!("SetupSP: {:?}", setup);
-
-    let mut game: Game = setup.into();
+    let mut game = setup_game(setup, params)?;
 
     // Support futures
     // game.setup_futures();
@@ -103,17 +125,27 @@ pub fn online_run(address: &str) -> PunterResult<Game> {
     debug!("Game: {:?}", game);
 
     // 3. loop
+    let mut ponder: Option<Ponder> = None;
     loop {
         let s = &io.read_json_message()?;
+        let start = Instant::now();
         let data: serde_json::Value = serde_json::from_str(s)?;
         assert!(data.is_object());
         let o = data.as_object().unwrap();
         if let Some(_) = o.get("move") {
             let game_play: OnlineGameplaySP = serde_json::from_str(s)?;
             game.apply_moves_excluding_me(game_play.move_.moves);
-            let mov = game.play(strategy);
+            let budget = game.time_control.budget(start);
+            let pondered = ponder.take().and_then(|ponder| ponder.finish(&game, &budget));
+            let mov = pondered.unwrap_or_else(|| watchdog::play(&game, strategy, &budget));
             game.apply_move(mov.clone());
             io.write_json_message(&serde_json::to_string(&mov)?)?;
+            game.time_control.on_move_sent(&mov);
+            ponder = Some(Ponder::start(&game, strategy));
+        } else if let Some(_) = o.get("timeout") {
+            let timeout: TimeoutSP = serde_json::from_str(s)?;
+            warn!("TimeoutSP: {:?}", timeout);
+            game.time_control.on_timeout();
         } else if let Some(_) = o.get("stop") {
             let scoring: OnlineScoringSP = serde_json::from_str(s)?;
             debug!("Scoring: {:?}", scoring);
@@ -127,3 +159,35 @@ pub fn online_run(address: &str) -> PunterResult<Game> {
     }
     Ok(game)
 }
+
+#[test]
+fn self_loop_test() {
+    use punter::arena;
+    use punter::budget::TimeBudget;
+    use std::time::Duration;
+
+    let mut map = arena::read_map(&arena::builtin_map_path("sample.json")).unwrap();
+    map.rivers.push(River { source: 3, target: 3 });
+    let setup = |map: Map| SetupSP {
+        punter: 0,
+        punters: 2,
+        map,
+        settings: None,
+    };
+    match setup_game(setup(map.clone()), Default::default()) {
+        Err(PunterError::InvalidMap(message)) => assert!(message.contains("self-loop river: 3-3")),
+        _ => panic!("a self-loop must be rejected"),
+    }
+
+    // The same map without the self-loop plays to the end.
+    let mut game = setup_game(setup(validate::normalize(&map)), Default::default()).unwrap();
+    for turn in 0..map.rivers.len() - 1 {
+        let mov = if turn % 2 == 0 {
+            game.play(Strategy::Stupid, &TimeBudget::new(Duration::from_millis(100)))
+        } else {
+            game.play_fallback()
+        };
+        game.apply_move(mov);
+    }
+    assert!(game.edges().iter().all(|e| !e.is_empty()));
+}
@@ -0,0 +1,174 @@
+use punter::budget::TimeBudget;
+use punter::game::{Claimed, Edge, EdgeClaim, Game, Strategy};
+use punter::opponent;
+use punter::prelude::*;
+use punter::protocol::*;
+use std::collections::HashSet;
+use std::sync::mpsc;
+use std::thread;
+use std::time::Duration;
+
+// Pondering stops after this long even if nobody cancels it.
+const MAX_PONDER_MILLIS: u64 = 30_000;
+// How long to wait for the pondering strategy after our budget is over.
+const GRACE_MILLIS: u64 = 50;
+
+// Thinks about our next move while the opponents think about theirs.
+// The opponents' moves are predicted by the opponent model and the strategy is run on the predicted
+// position. If the opponents play as predicted, the strategy keeps running until our budget is over and its
+// move is used. With many opponents some predictions are usually wrong, so the move is also used if it is still
+// legal and the rivers where the prediction was wrong are away from it. Otherwise the analysis is thrown away.
+pub struct Ponder {
+    strategy: Strategy,
+    budget: TimeBudget,
+    position: mpsc::Receiver<Vec<Claimed>>,
+    mov: mpsc::Receiver<Move>,
+}
+
+impl Ponder {
+    // Starts pondering. `game` is the position right after our move.
+    pub fn start(game: &Game, strategy: Strategy) -> Ponder {
+        let (position_tx, position) = mpsc::channel();
+        let (mov_tx, mov) = mpsc::channel();
+        let budget = TimeBudget::new(Duration::from_millis(MAX_PONDER_MILLIS));
+        let ponder_budget = budget.clone();
+        let mut predicted = game.clone();
+        let spawned = thread::Builder::new()
+            .name(format!("ponder {:?}", strategy))
+            .spawn(move || {
+                let me = predicted.me;
+                for i in 1..predicted.punters() {
+                    let p = (me + i) % predicted.punters();
+                    match opponent::predict_claim(&predicted, p) {
+                        Some(index) => {
+                            let e = predicted.edges()[index].clone();
+                            predicted.apply_edge_claim(EdgeClaim::new(p, e.source, e.target), false);
+                        }
+                        None => return,
+                    }
+                }
+                let claimed = predicted.edges().iter().map(|e| e.claimed.clone()).collect();
+                if position_tx.send(claimed).is_err() {
+                    return;
+                }
+                let _ = mov_tx.send(predicted.play(strategy, &ponder_budget));
+            });
+        if let Err(e) = spawned {
+            warn!("ponder: failed to spawn a thread: {}", e);
+        }
+        Ponder {
+            strategy,
+            budget,
+            position,
+            mov,
+        }
+    }
+
+    // Returns the pondered move if it is still good in `game`, the position after the opponents' moves.
+    pub fn finish(self, game: &Game, budget: &TimeBudget) -> Option<Move> {
+        let predicted = match self.position.recv_timeout(budget.remaining()) {
+            Ok(predicted) => predicted,
+            Err(_) => {
+                debug!("ponder: no prediction");
+                return None;
+            }
+        };
+        let wrong = game.edges()
+            .iter()
+            .zip(predicted.iter())
+            .enumerate()
+            .filter(|&(_, (e, claimed))| e.claimed != *claimed)
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+        if wrong.is_empty() {
+            debug!("ponder: hit");
+            return self.wait(budget.remaining());
+        }
+        // The move is not sure to be used, so half of the budget is left for thinking again.
+        let mov = match self.wait(budget.remaining() / 2) {
+            Some(mov) => mov,
+            None => return None,
+        };
+        if game.is_legal_move(&mov) && !touches(game, &mov, &wrong) {
+            debug!("ponder: partial hit ({} rivers mispredicted)", wrong.len());
+            Some(mov)
+        } else {
+            debug!("ponder: miss");
+            None
+        }
+    }
+
+    fn wait(&self, timeout: Duration) -> Option<Move> {
+        match self.mov.recv_timeout(timeout) {
+            Ok(mov) => Some(mov),
+            Err(mpsc::RecvTimeoutError::Timeout) => {
+                self.budget.cancel();
+                self.mov.recv_timeout(Duration::from_millis(GRACE_MILLIS)).ok()
+            }
+            Err(mpsc::RecvTimeoutError::Disconnected) => {
+                warn!("ponder: {:?} panicked", self.strategy);
+                None
+            }
+        }
+    }
+}
+
+// Whether any of the `edges` has an end at a site of `mov` or next to one. A pass touches everything.
+fn touches(game: &Game, mov: &Move, edges: &[usize]) -> bool {
+    let sites: Vec<SiteId> = match *mov {
+        Move::Claim { ref claim } => vec![claim.source, claim.target],
+        Move::Option_ { ref option } => vec![option.source, option.target],
+        Move::Splurge { ref splurge } => splurge.route.clone(),
+        Move::Pass { .. } => return true,
+    };
+    let ends = |e: &Edge| (game.node_to_site_id(e.source), game.node_to_site_id(e.target));
+    let mut near: HashSet<SiteId> = sites.iter().cloned().collect();
+    for (s, t) in game.edges().iter().map(&ends) {
+        if sites.contains(&s) || sites.contains(&t) {
+            near.insert(s);
+            near.insert(t);
+        }
+    }
+    edges.iter().map(|&index| ends(&game.edges()[index])).any(|(s, t)| near.contains(&s) || near.contains(&t))
+}
+
+impl Drop for Ponder {
+    fn drop(&mut self) {
+        self.budget.cancel();
+    }
+}
+
+#[test]
+fn ponder_test() {
+    use punter::arena;
+    use serde_json;
+
+    let mut game = arena::sample_game("lambda.json");
+    game.apply_edge_claim(EdgeClaim::new(0, 4, 21), false);
+    let predicted = opponent::predict_claim(&game, 1).unwrap();
+    let e = game.edges()[predicted].clone();
+    let mut predicted_game = game.clone();
+    predicted_game.apply_edge_claim(EdgeClaim::new(1, e.source, e.target), false);
+    let budget = || TimeBudget::new(Duration::from_millis(500));
+    let pondered = predicted_game.play(Strategy::EdgeWeight, &budget());
+    let json = |m: &Move| serde_json::to_string(m).unwrap();
+
+    assert!(Ponder::start(&game, Strategy::EdgeWeight).finish(&predicted_game, &budget()).is_some());
+
+    // The opponent claims another river, away from the pondered move.
+    let far = (0..game.edges().len())
+        .find(|i| *i != predicted && game.edges()[*i].is_empty() && !touches(&game, &pondered, &[predicted, *i]))
+        .unwrap();
+    let mut partial = game.clone();
+    let e = game.edges()[far].clone();
+    partial.apply_edge_claim(EdgeClaim::new(1, e.source, e.target), false);
+    let mov = Ponder::start(&game, Strategy::EdgeWeight).finish(&partial, &budget());
+    assert_eq!(mov.map(|m| json(&m)), Some(json(&pondered)));
+
+    // The opponent takes the pondered river.
+    let mut miss = game.clone();
+    if let Move::Claim { ref claim } = pondered {
+        miss.apply_move(Move::from(Claim { punter: 1, ..claim.clone() }));
+    }
+    assert!(Ponder::start(&game, Strategy::EdgeWeight).finish(&miss, &budget()).is_none());
+}
@@ -16,6 +16,14 @@ quick_error! {
         Json(err: serde_json::Error) {
             from()
         }
+        InvalidArgument(msg: String) {
+            description(msg)
+            display("invalid argument: {}", msg)
+        }
+        InvalidMap(msg: String) {
+            description(msg)
+            display("invalid map: {}", msg)
+        }
     }
 }
 
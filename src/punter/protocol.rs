@@ -49,13 +49,65 @@ impl std::default::Default for Settings {
     }
 }
 
+const EXTENSIONS: [&'static str; 3] = ["futures", "splurge", "options"];
+
+impl Settings {
+    fn enabled(&self) -> [bool; 3] {
+        [
+            self.futures.unwrap_or(false),
+            self.splurge.unwrap_or(false),
+            self.options.unwrap_or(false),
+        ]
+    }
+}
+
+// "none", "all", or enabled extensions joined with '+', e.g. "futures+options".
+impl std::fmt::Display for Settings {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let enabled = self.enabled();
+        let names = EXTENSIONS
+            .iter()
+            .zip(enabled.iter())
+            .filter(|&(_, &on)| on)
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>();
+        match names.len() {
+            0 => write!(f, "none"),
+            3 => write!(f, "all"),
+            _ => write!(f, "{}", names.join("+")),
+        }
+    }
+}
+
+impl str::FromStr for Settings {
+    type Err = PunterError;
+
+    fn from_str(s: &str) -> PunterResult<Settings> {
+        let mut settings = Settings::default();
+        let names: Vec<&str> = match s {
+            "none" => vec![],
+            "all" => EXTENSIONS.to_vec(),
+            _ => s.split('+').collect(),
+        };
+        for name in names {
+            match name {
+                "futures" => settings.futures = Some(true),
+                "splurge" => settings.splurge = Some(true),
+                "options" => settings.options = Some(true),
+                _ => return Err(PunterError::InvalidArgument(format!("unknown extension: {}", name))),
+            }
+        }
+        Ok(settings)
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct OnlineSetupPS {
     pub ready: PunterId,
     pub futures: Option<Vec<Future>>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Future {
     pub source: SiteId,
     pub target: SiteId,
@@ -110,14 +162,17 @@ impl Move {
     pub fn from(claim: Claim) -> Self {
         Move::Claim { claim }
     }
-    pub fn claimed_by(&self, me: PunterId) -> bool {
+    pub fn punter(&self) -> PunterId {
         match *self {
-            Move::Claim { ref claim } => claim.punter == me,
-            Move::Splurge { ref splurge } => splurge.punter == me,
-            Move::Option_ { ref option } => option.punter == me,
-            Move::Pass { ref pass } => pass.punter == me,
+            Move::Claim { ref claim } => claim.punter,
+            Move::Splurge { ref splurge } => splurge.punter,
+            Move::Option_ { ref option } => option.punter,
+            Move::Pass { ref pass } => pass.punter,
         }
     }
+    pub fn claimed_by(&self, me: PunterId) -> bool {
+        self.punter() == me
+    }
 }
 
 // 3. Scoring
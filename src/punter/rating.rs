@@ -0,0 +1,162 @@
+use punter::prelude::*;
+use serde_json;
+use std;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+// TrueSkill's defaults.
+const INITIAL_MU: f64 = 25.0;
+const INITIAL_SIGMA: f64 = INITIAL_MU / 3.0;
+const BETA: f64 = INITIAL_SIGMA / 2.0;
+// Keeps sigma from collapsing after a very surprising result.
+const KAPPA: f64 = 0.0001;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Rating {
+    pub mu: f64,
+    // The uncertainty of mu.
+    pub sigma: f64,
+    pub games: usize,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Rating {
+            mu: INITIAL_MU,
+            sigma: INITIAL_SIGMA,
+            games: 0,
+        }
+    }
+}
+
+impl Rating {
+    // Very likely below the true skill, so that bots with few games do not top the leaderboard by luck.
+    pub fn conservative(&self) -> f64 {
+        self.mu - 3.0 * self.sigma
+    }
+}
+
+// Ratings of bots by name, kept in a JSON file across runs.
+//
+// A battle is rated as a match between every pair of its bots, with the Bradley-Terry model of Weng and Lin,
+// "A Bayesian Approximation Method for Online Ranking" (2011). It is TrueSkill without factor graphs, and
+// handles any number of punters and ties in closed form.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Ratings {
+    pub bots: BTreeMap<String, Rating>,
+}
+
+impl Ratings {
+    // No ratings if the file does not exist yet.
+    pub fn load<P: AsRef<Path>>(path: P) -> PunterResult<Ratings> {
+        match fs::File::open(path.as_ref()) {
+            Ok(f) => Ok(serde_json::from_reader(f)?),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Ok(Default::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> PunterResult<()> {
+        serde_json::to_writer_pretty(fs::File::create(path.as_ref())?, self)?;
+        Ok(())
+    }
+
+    pub fn get(&self, name: &str) -> Rating {
+        self.bots.get(name).cloned().unwrap_or_default()
+    }
+
+    // Updates ratings with the result of a battle, given as (name, score) by seat. A bot seated more than once
+    // is rated once, with its mean score.
+    pub fn update(&mut self, result: &[(String, i64)]) {
+        let mut scores: BTreeMap<&str, Vec<i64>> = BTreeMap::new();
+        for &(ref name, score) in result {
+            scores.entry(name.as_str()).or_insert_with(Vec::new).push(score);
+        }
+        let players = scores
+            .into_iter()
+            .map(|(name, s)| (name, s.iter().sum::<i64>() as f64 / s.len() as f64, self.get(name)))
+            .collect::<Vec<_>>();
+
+        let mut updated = vec![];
+        for &(name, score, r) in players.iter() {
+            let (mut omega, mut delta) = (0.0, 0.0);
+            for &(other, other_score, q) in players.iter() {
+                if other == name {
+                    continue;
+                }
+                let c = (r.sigma * r.sigma + q.sigma * q.sigma + 2.0 * BETA * BETA).sqrt();
+                // The probability that this bot beats the other one.
+                let p = 1.0 / (1.0 + ((q.mu - r.mu) / c).exp());
+                let s = if score > other_score {
+                    1.0
+                } else if score < other_score {
+                    0.0
+                } else {
+                    0.5
+                };
+                omega += r.sigma * r.sigma / c * (s - p);
+                let gamma = r.sigma / c;
+                delta += gamma * r.sigma * r.sigma / (c * c) * p * (1.0 - p);
+            }
+            updated.push((
+                name.to_string(),
+                Rating {
+                    mu: r.mu + omega,
+                    sigma: r.sigma * (1.0 - delta).max(KAPPA).sqrt(),
+                    games: r.games + 1,
+                },
+            ));
+        }
+        self.bots.extend(updated);
+    }
+}
+
+// Ranked by the conservative rating.
+impl fmt::Display for Ratings {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut bots = self.bots.iter().collect::<Vec<_>>();
+        bots.sort_by(|a, b| b.1.conservative().partial_cmp(&a.1.conservative()).unwrap());
+        for (i, (name, r)) in bots.into_iter().enumerate() {
+            writeln!(
+                f,
+                "{:3}. {:>32}, rating: {:6.2} (mu: {:6.2} +/- {:5.2}), games: {}",
+                i + 1,
+                name,
+                r.conservative(),
+                r.mu,
+                r.sigma,
+                r.games
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn ratings_test() {
+    let result = |scores: &[(&str, i64)]| scores.iter().map(|&(n, s)| (n.to_string(), s)).collect::<Vec<_>>();
+    let mut ratings = Ratings::default();
+    for _ in 0..20 {
+        ratings.update(&result(&[("a", 30), ("b", 20), ("c", 10)]));
+    }
+    let (a, b, c) = (ratings.get("a"), ratings.get("b"), ratings.get("c"));
+    assert!(a.mu > b.mu && b.mu > c.mu);
+    assert!(a.sigma < INITIAL_SIGMA);
+    assert_eq!(a.games, 20);
+
+    // A tie between equals changes nothing but the uncertainty, and a bot playing itself is rated once.
+    let mut ratings = Ratings::default();
+    ratings.update(&result(&[("a", 5), ("b", 5), ("b", 7)]));
+    assert!(ratings.get("a").mu < INITIAL_MU);
+    ratings.update(&result(&[("x", 1), ("y", 1)]));
+    assert_eq!(ratings.get("x").mu, INITIAL_MU);
+    assert!(ratings.get("x").sigma < INITIAL_SIGMA);
+    assert_eq!(ratings.get("b").games, 1);
+
+    let leaderboard = ratings.to_string();
+    assert!(leaderboard.starts_with("  1.                                b, rating:"));
+    assert_eq!(leaderboard.lines().count(), 4);
+}
@@ -0,0 +1,371 @@
+use punter::budget::TimeBudget;
+use punter::game::{EdgeClaim, EdgeIndex, Game, Node};
+use punter::parallel;
+use punter::prelude::*;
+use std::cmp;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+const MAX_DEPTH: usize = 32;
+// Rivers considered for each punter, ordered by the punter's EdgeWeight potential.
+const CANDIDATES_PER_PUNTER: usize = 8;
+// The endgame search starts when the number of rivers which can still change a score drops under this.
+const ENDGAME_RIVERS: usize = 10;
+const ENDGAME_MAX_DEPTH: usize = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Bound {
+    Exact,
+    Lower,
+    Upper,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TtEntry {
+    depth: usize,
+    value: i64,
+    bound: Bound,
+    best: Option<EdgeIndex>,
+}
+
+// Alpha-beta search over claims.
+// With two punters this is plain minimax on the score difference. With more punters, all opponents are
+// assumed to cooperate against us (paranoid search) and we maximize our lead over the best opponent.
+struct Search {
+    game: Game,
+    candidates: Vec<Vec<EdgeIndex>>, // punter -> rivers, best first
+    tt: HashMap<u64, TtEntry>,
+    hash: u64,
+    budget: TimeBudget,
+    nodes: u64,
+    aborted: bool,
+}
+
+fn zobrist(edge: EdgeIndex, p: PunterId) -> u64 {
+    // splitmix64
+    let mut z = (edge as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ (p as u64 + 1).wrapping_mul(0xD6E8_FEB8_6659_FD93);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+impl Search {
+    fn new(game: &Game, budget: &TimeBudget) -> Search {
+        let candidates = parallel::map((0..game.punters()).collect(), |p| {
+            let mut weighted = game.calc_edge_weights(p)
+                .into_iter()
+                .enumerate()
+                .filter(|&(index, _)| game.edges()[index].is_empty())
+                .collect::<Vec<_>>();
+            weighted.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(cmp::Ordering::Equal).then(a.0.cmp(&b.0)));
+            weighted.into_iter().take(CANDIDATES_PER_PUNTER).map(|(index, _)| index).collect()
+        });
+        Search {
+            game: game.clone(),
+            candidates,
+            tt: HashMap::new(),
+            hash: 0,
+            budget: budget.clone(),
+            nodes: 0,
+            aborted: false,
+        }
+    }
+
+    fn evaluate(&self) -> i64 {
+        let me = self.game.me;
+        let best_opponent = (0..self.game.punters())
+            .filter(|p| *p != me)
+            .map(|p| self.game.score(p))
+            .max()
+            .unwrap_or(0);
+        self.game.score(me) - best_opponent
+    }
+
+    fn moves(&self, p: PunterId, tt_best: Option<EdgeIndex>) -> Vec<EdgeIndex> {
+        let mut moves: Vec<EdgeIndex> = tt_best.into_iter().collect();
+        for &index in self.candidates[p].iter() {
+            if self.game.edges()[index].is_empty() && Some(index) != tt_best {
+                moves.push(index);
+            }
+        }
+        moves
+    }
+
+    fn make(&mut self, index: EdgeIndex, p: PunterId) {
+        self.game.claim_edge(index, p);
+        self.hash ^= zobrist(index, p);
+    }
+
+    fn unmake(&mut self, index: EdgeIndex, p: PunterId) {
+        self.game.unclaim_edge(index);
+        self.hash ^= zobrist(index, p);
+    }
+
+    fn is_timeout(&mut self) -> bool {
+        self.nodes += 1;
+        if !self.aborted && self.nodes % 64 == 0 && self.budget.is_over() {
+            self.aborted = true;
+        }
+        self.aborted
+    }
+
+    fn alphabeta(&mut self, depth: usize, ply: usize, mut alpha: i64, mut beta: i64) -> i64 {
+        if self.is_timeout() {
+            return 0;
+        }
+        let p = (self.game.me + ply) % self.game.punters();
+        let tt_entry = self.tt.get(&self.hash).cloned();
+        if let Some(entry) = tt_entry {
+            if entry.depth >= depth {
+                match entry.bound {
+                    Bound::Exact => return entry.value,
+                    Bound::Lower => alpha = cmp::max(alpha, entry.value),
+                    Bound::Upper => beta = cmp::min(beta, entry.value),
+                }
+                if alpha >= beta {
+                    return entry.value;
+                }
+            }
+        }
+        let moves = self.moves(p, tt_entry.and_then(|e| e.best));
+        if depth == 0 || moves.is_empty() {
+            return self.evaluate();
+        }
+
+        let (alpha_orig, beta_orig) = (alpha, beta);
+        let maximizing = p == self.game.me;
+        let mut value = if maximizing { i64::min_value() } else { i64::max_value() };
+        let mut best = None;
+        for index in moves {
+            self.make(index, p);
+            let v = self.alphabeta(depth - 1, ply + 1, alpha, beta);
+            self.unmake(index, p);
+            if self.aborted {
+                return 0;
+            }
+            if maximizing {
+                if v > value {
+                    value = v;
+                    best = Some(index);
+                }
+                alpha = cmp::max(alpha, v);
+            } else {
+                if v < value {
+                    value = v;
+                    best = Some(index);
+                }
+                beta = cmp::min(beta, v);
+            }
+            if alpha >= beta {
+                break;
+            }
+        }
+
+        let bound = if value <= alpha_orig {
+            Bound::Upper
+        } else if value >= beta_orig {
+            Bound::Lower
+        } else {
+            Bound::Exact
+        };
+        self.tt.insert(
+            self.hash,
+            TtEntry {
+                depth,
+                value,
+                bound,
+                best,
+            },
+        );
+        value
+    }
+
+    // Iterative deepening. Returns the best move of the deepest completed iteration.
+    fn run(&mut self) -> Option<EdgeIndex> {
+        let remaining_turns = self.game.edges().iter().filter(|e| e.is_empty()).count();
+        let mut best = None;
+        for depth in 1..cmp::min(MAX_DEPTH, remaining_turns) + 1 {
+            self.alphabeta(depth, 0, i64::min_value(), i64::max_value());
+            if self.aborted {
+                break;
+            }
+            best = self.tt.get(&self.hash).and_then(|e| e.best);
+            debug!("minimax: depth {}, best {:?}, nodes {}", depth, best, self.nodes);
+        }
+        best
+    }
+}
+
+pub fn play_minimax(game: &Game, budget: &TimeBudget) -> EdgeClaim {
+    match Search::new(game, budget).run() {
+        Some(index) => {
+            let e = &game.edges()[index];
+            EdgeClaim::new(game.me, e.source, e.target)
+        }
+        None => game.play_edge_weight(),
+    }
+}
+
+// Free rivers which can still change someone's score, i.e. rivers touching a site which some punter can
+// reach from a mine through rivers which are free or owned by the punter.
+pub fn relevant_free_rivers(game: &Game) -> Vec<EdgeIndex> {
+    let edges = game.edges();
+    let mut relevant = vec![false; edges.len()];
+    for p in 0..game.punters() {
+        let mut visited = vec![false; game.site_count()];
+        let mut q: VecDeque<Node> = game.mines().iter().cloned().collect();
+        for mine in game.mines() {
+            visited[*mine] = true;
+        }
+        while let Some(s) = q.pop_front() {
+            for adj in game.adj_edges(s) {
+                let e = &edges[adj.edge_index];
+                if e.is_empty() {
+                    relevant[adj.edge_index] = true;
+                } else if !e.claimed.is_owned_by(p) {
+                    continue;
+                }
+                if !visited[adj.target] {
+                    visited[adj.target] = true;
+                    q.push_back(adj.target);
+                }
+            }
+        }
+    }
+    (0..edges.len()).filter(|index| relevant[*index]).collect()
+}
+
+// Max^n search over the remaining relevant rivers: every punter maximizes their own score.
+// Irrelevant free rivers are modelled as a single "waste a turn" move.
+struct Endgame {
+    game: Game,
+    rivers: Vec<EdgeIndex>,
+    irrelevant: usize,
+    budget: TimeBudget,
+    nodes: u64,
+    aborted: bool,
+}
+
+impl Endgame {
+    fn scores(&self) -> Vec<i64> {
+        (0..self.game.punters()).map(|p| self.game.score(p)).collect()
+    }
+
+    fn is_timeout(&mut self) -> bool {
+        self.nodes += 1;
+        if !self.aborted && self.nodes % 16 == 0 && self.budget.is_over() {
+            self.aborted = true;
+        }
+        self.aborted
+    }
+
+    // Returns the scores at the end of the principal variation, and our move at the root.
+    fn maxn(&mut self, depth: usize, ply: usize) -> (Vec<i64>, Option<EdgeIndex>) {
+        if self.is_timeout() {
+            return (vec![], None);
+        }
+        let p = (self.game.me + ply) % self.game.punters();
+        let moves: Vec<Option<EdgeIndex>> = self.rivers
+            .iter()
+            .filter(|index| self.game.edges()[**index].is_empty())
+            .map(|index| Some(*index))
+            .chain(if self.irrelevant > 0 { Some(None) } else { None })
+            .collect();
+        if depth == 0 || moves.iter().all(|m| m.is_none()) {
+            return (self.scores(), None);
+        }
+
+        let mut best: Option<(Vec<i64>, Option<EdgeIndex>)> = None;
+        for m in moves {
+            match m {
+                Some(index) => self.game.claim_edge(index, p),
+                None => self.irrelevant -= 1,
+            }
+            let (v, _) = self.maxn(depth - 1, ply + 1);
+            match m {
+                Some(index) => self.game.unclaim_edge(index),
+                None => self.irrelevant += 1,
+            }
+            if self.aborted {
+                return (vec![], None);
+            }
+            let better = match best {
+                None => true,
+                Some((ref b, _)) => {
+                    // Ties are broken paranoidly: prefer the line which is worse for the others.
+                    let others = |s: &[i64]| s.iter().sum::<i64>() - s[p];
+                    v[p] > b[p] || v[p] == b[p] && others(&v) < others(b)
+                }
+            };
+            if better {
+                best = Some((v, m));
+            }
+        }
+        best.unwrap()
+    }
+}
+
+// Solves the rest of the game exactly (or to a depth limit) once few relevant rivers remain.
+// Returns None while the game is not yet in the endgame.
+pub fn play_endgame(game: &Game, budget: &TimeBudget) -> Option<EdgeClaim> {
+    let rivers = relevant_free_rivers(game);
+    if rivers.is_empty() || rivers.len() >= ENDGAME_RIVERS {
+        return None;
+    }
+    let free = game.edges().iter().filter(|e| e.is_empty()).count();
+    let mut endgame = Endgame {
+        game: game.clone(),
+        irrelevant: free - rivers.len(),
+        rivers,
+        budget: budget.clone(),
+        nodes: 0,
+        aborted: false,
+    };
+
+    // Some(None) if the best move is to wait, on an irrelevant river.
+    let mut best = None;
+    for depth in 1..cmp::min(ENDGAME_MAX_DEPTH, free) + 1 {
+        let (scores, m) = endgame.maxn(depth, 0);
+        if endgame.aborted {
+            break;
+        }
+        debug!("endgame: depth {}, scores {:?}, move {:?}, nodes {}", depth, scores, m, endgame.nodes);
+        best = Some(m);
+    }
+    let index = match best {
+        Some(Some(index)) => index,
+        Some(None) => {
+            (0..game.edges().len())
+                .find(|index| game.edges()[*index].is_empty() && !endgame.rivers.contains(index))
+                .unwrap()
+        }
+        None => return None,
+    };
+    let e = &game.edges()[index];
+    Some(EdgeClaim::new(game.me, e.source, e.target))
+}
+
+#[test]
+fn endgame_wait_test() {
+    use punter::protocol::*;
+    use std::time::Duration;
+
+    // The first punter does best by wasting its first turn on 2-5, the only river no one can score with.
+    let map: Map = ::serde_json::from_str(
+        r#"{"sites": [{"id": 0, "x": 0.0, "y": 0.0}, {"id": 1, "x": 1.0, "y": 0.0}, {"id": 2, "x": 2.0, "y": 0.0},
+                      {"id": 3, "x": 3.0, "y": 0.0}, {"id": 4, "x": 4.0, "y": 0.0}, {"id": 5, "x": 5.0, "y": 0.0},
+                      {"id": 6, "x": 6.0, "y": 0.0}, {"id": 7, "x": 7.0, "y": 0.0}],
+            "rivers": [{"source": 7, "target": 3}, {"source": 1, "target": 4}, {"source": 4, "target": 0},
+                       {"source": 6, "target": 4}, {"source": 2, "target": 5}, {"source": 3, "target": 1},
+                       {"source": 7, "target": 0}, {"source": 6, "target": 4}],
+            "mines": [0, 7]}"#,
+    ).unwrap();
+    let game: Game = SetupSP {
+        punter: 0,
+        punters: 2,
+        map,
+        settings: None,
+    }.into();
+    let claim = play_endgame(&game, &TimeBudget::new(Duration::from_secs(10))).unwrap();
+    assert_eq!((game.node_to_site_id(claim.source), game.node_to_site_id(claim.target)), (2, 5));
+}
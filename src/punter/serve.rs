@@ -0,0 +1,300 @@
+use punter::arena::Listener;
+use punter::game::Game;
+use punter::prelude::*;
+use punter::protocol::*;
+use serde_json;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, mpsc};
+use std::thread;
+
+// Server-sent events of one battle. A client joining late gets the events it missed first. Streams end with the
+// battle, when the senders to clients are dropped.
+pub struct LiveFeed {
+    battle: Option<usize>,
+    game: Option<Game>,
+    history: Vec<String>,
+    clients: Vec<mpsc::Sender<String>>,
+    stopped: bool,
+}
+
+impl LiveFeed {
+    pub fn new() -> LiveFeed {
+        LiveFeed {
+            battle: None,
+            game: None,
+            history: vec![],
+            clients: vec![],
+            stopped: false,
+        }
+    }
+
+    fn publish(&mut self, event: &str, data: serde_json::Value) {
+        let message = format!("event: {}\ndata: {}\n\n", event, data);
+        self.clients.retain(|client| client.send(message.clone()).is_ok());
+        self.history.push(message);
+    }
+
+    fn subscribe(&mut self) -> mpsc::Receiver<String> {
+        let (sender, receiver) = mpsc::channel();
+        for message in self.history.iter() {
+            sender.send(message.clone()).unwrap();
+        }
+        if !self.stopped {
+            self.clients.push(sender);
+        }
+        receiver
+    }
+
+    fn scores(&self) -> Vec<i64> {
+        match self.game {
+            Some(ref game) => (0..game.punters()).map(|p| game.score(p)).collect(),
+            None => vec![],
+        }
+    }
+}
+
+// Only the first battle to start is fed.
+impl Listener for LiveFeed {
+    fn start(&mut self, battle: usize, map: &Map, names: &[String]) {
+        if self.battle.is_some() {
+            return;
+        }
+        self.battle = Some(battle);
+        self.game = Some(
+            SetupSP {
+                punter: 0,
+                punters: names.len(),
+                map: map.clone(),
+                settings: None,
+            }.into(),
+        );
+        self.publish("start", json!({ "map": map, "punters": names }));
+    }
+
+    fn play(&mut self, battle: usize, mov: &Move) {
+        if self.battle != Some(battle) {
+            return;
+        }
+        if let Some(ref mut game) = self.game {
+            if game.is_legal_move(mov) {
+                game.apply_move(mov.clone());
+            }
+        }
+        let scores = self.scores();
+        self.publish("move", json!({ "move": mov, "scores": scores }));
+    }
+
+    fn stop(&mut self, battle: usize, scores: &[i64]) {
+        if self.battle == Some(battle) {
+            self.publish("stop", json!({ "scores": scores }));
+            self.stopped = true;
+            self.clients.clear();
+        }
+    }
+}
+
+pub struct Server {
+    pub visualizer_dir: PathBuf,
+    pub record_dir: PathBuf,
+    pub feed: Arc<Mutex<LiveFeed>>,
+}
+
+fn content_type(path: &Path) -> &'static str {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("js") => "application/javascript",
+        Some("css") => "text/css",
+        Some("json") => "application/json",
+        Some("svg") => "image/svg+xml",
+        _ => "application/octet-stream",
+    }
+}
+
+// A file under `dir`. Paths going out of `dir` are refused.
+fn file_under(dir: &Path, relative: &str) -> Option<PathBuf> {
+    if relative.split('/').any(|c| c.starts_with('.')) {
+        return None;
+    }
+    let path = dir.join(relative.trim_left_matches('/'));
+    if path.is_dir() {
+        Some(path.join("index.html"))
+    } else {
+        Some(path)
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str, content_type: &str, body: &[u8]) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(body)
+}
+
+fn respond_file(stream: &mut TcpStream, path: Option<PathBuf>) -> io::Result<()> {
+    let mut body = vec![];
+    match path.and_then(|path| fs::File::open(&path).ok().map(|f| (path, f))) {
+        Some((path, mut f)) => {
+            f.read_to_end(&mut body)?;
+            respond(stream, "200 OK", content_type(&path), &body)
+        }
+        None => respond(stream, "404 Not Found", "text/plain", b"not found"),
+    }
+}
+
+impl Server {
+    // Recorded games, newest first.
+    pub fn games(&self) -> Vec<String> {
+        let mut games = fs::read_dir(&self.record_dir)
+            .map(|entries| {
+                entries
+                    .filter_map(|e| e.ok())
+                    .filter_map(|e| e.file_name().into_string().ok())
+                    .filter(|name| name.ends_with(".json") && name != "latest.json")
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        games.sort();
+        games.reverse();
+        games
+    }
+
+    fn index(&self) -> String {
+        let mut html = "<!DOCTYPE html>\n<meta charset=\"utf-8\">\n<title>icfp2017</title>\n<ul>\n".to_string();
+        if self.feed.lock().unwrap().battle.is_some() {
+            html += "<li><a href=\"/visualizer/?live=/live\">live</a></li>\n";
+        }
+        for game in self.games() {
+            html += &format!("<li><a href=\"/visualizer/?map=/records/{0}\">{0}</a></li>\n", game);
+        }
+        html + "</ul>\n"
+    }
+
+    fn stream_live(&self, stream: &mut TcpStream) -> io::Result<()> {
+        write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n"
+        )?;
+        let receiver = self.feed.lock().unwrap().subscribe();
+        for message in receiver.iter() {
+            stream.write_all(message.as_bytes())?;
+            stream.flush()?;
+        }
+        Ok(())
+    }
+
+    fn handle(&self, mut stream: TcpStream) -> io::Result<()> {
+        let mut request_line = String::new();
+        let mut reader = BufReader::new(stream.try_clone()?);
+        reader.read_line(&mut request_line)?;
+        loop {
+            let mut header = String::new();
+            if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+                break;
+            }
+        }
+        let mut words = request_line.split_whitespace();
+        let (method, target) = (words.next().unwrap_or(""), words.next().unwrap_or("/"));
+        let path = target.split('?').next().unwrap();
+        debug!("serve: {} {}", method, target);
+        if method != "GET" {
+            return respond(&mut stream, "405 Method Not Allowed", "text/plain", b"method not allowed");
+        }
+        if path == "/" {
+            respond(&mut stream, "200 OK", "text/html; charset=utf-8", self.index().as_bytes())
+        } else if path == "/games" {
+            respond(&mut stream, "200 OK", "application/json", serde_json::to_string(&self.games()).unwrap().as_bytes())
+        } else if path == "/live" {
+            self.stream_live(&mut stream)
+        } else if path.starts_with("/records/") {
+            respond_file(&mut stream, file_under(&self.record_dir, &path["/records/".len()..]))
+        } else if path.starts_with("/visualizer/") {
+            respond_file(&mut stream, file_under(&self.visualizer_dir, &path["/visualizer/".len()..]))
+        } else {
+            respond_file(&mut stream, None)
+        }
+    }
+
+    // Serves connections on their own threads, forever.
+    pub fn run(self, listener: TcpListener) {
+        let server = Arc::new(self);
+        for stream in listener.incoming() {
+            match stream {
+                Ok(stream) => {
+                    let server = server.clone();
+                    thread::spawn(move || if let Err(e) = server.handle(stream) {
+                        debug!("serve: {}", e);
+                    });
+                }
+                Err(e) => warn!("serve: {}", e),
+            }
+        }
+    }
+}
+
+// Serves on localhost only.
+pub fn serve_run(port: u16, server: Server) -> PunterResult<()> {
+    let listener = TcpListener::bind(("127.0.0.1", port))?;
+    println!("serving on http://{}/", listener.local_addr()?);
+    server.run(listener);
+    Ok(())
+}
+
+#[test]
+fn serve_test() {
+    use std::time::Duration;
+
+    let get = |port: u16, target: &str| -> TcpStream {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", target).unwrap();
+        stream
+    };
+    let read_all = |mut stream: TcpStream| {
+        let mut s = String::new();
+        stream.read_to_string(&mut s).unwrap();
+        s
+    };
+
+    let feed = Arc::new(Mutex::new(LiveFeed::new()));
+    let server = Server {
+        visualizer_dir: PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/visualizer")),
+        record_dir: PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/visualizer")),
+        feed: feed.clone(),
+    };
+    let listener = TcpListener::bind(("127.0.0.1", 0)).unwrap();
+    let port = listener.local_addr().unwrap().port();
+    thread::spawn(move || server.run(listener));
+
+    assert!(read_all(get(port, "/visualizer/?map=x")).starts_with("HTTP/1.1 200 OK"));
+    assert!(read_all(get(port, "/visualizer/../Cargo.toml")).starts_with("HTTP/1.1 404"));
+    assert!(read_all(get(port, "/games")).contains("\"oxford-log.json\""));
+
+    let map = Map {
+        sites: (0..2).map(|id| Site { id, x: 0.0, y: 0.0 }).collect(),
+        rivers: vec![River { source: 0, target: 1 }],
+        mines: vec![0],
+    };
+    feed.lock().unwrap().start(0, &map, &["a".to_string()]);
+    let live = get(port, "/live");
+    feed.lock().unwrap().play(0, &Move::from(Claim { punter: 0, source: 0, target: 1 }));
+    feed.lock().unwrap().stop(0, &[1]);
+
+    // Both a client which watched the battle and one joining after it get all of it, and then the end of the stream.
+    for live in vec![live, get(port, "/live")] {
+        let body = read_all(live);
+        let events = body
+            .lines()
+            .filter(|line| line.starts_with("event: "))
+            .map(|line| &line["event: ".len()..])
+            .collect::<Vec<_>>();
+        assert_eq!(events, vec!["start", "move", "stop"]);
+        assert!(body.contains("\"scores\":[1]"));
+    }
+}
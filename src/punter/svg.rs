@@ -0,0 +1,230 @@
+use punter::arena::{self, VisGraph};
+use punter::game::{Claimed, Game};
+use punter::graph_io::punter_color;
+use punter::prelude::*;
+use punter::protocol::*;
+use serde_json;
+use std;
+use std::cmp;
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+const MAP_WIDTH: f64 = 1000.0;
+const MIN_MAP_HEIGHT: f64 = 300.0;
+const MAX_MAP_HEIGHT: f64 = 1600.0;
+const PADDING: f64 = 30.0;
+const LEGEND_WIDTH: f64 = 240.0;
+// Distance between parallel rivers, in pixels.
+const LANE_OFFSET: f64 = 8.0;
+
+// The smallest and largest coordinates of sites, as ((xmin, ymin), (xmax, ymax)).
+pub fn bounding_box(sites: &[Site]) -> ((f64, f64), (f64, f64)) {
+    let infinity = std::f64::INFINITY;
+    sites.iter().fold(((infinity, infinity), (-infinity, -infinity)), |(lo, hi), s| {
+        ((s.x.min(lo.0), s.y.min(lo.1)), (s.x.max(hi.0), s.y.max(hi.1)))
+    })
+}
+
+// Maps site coordinates to pixels, keeping the aspect ratio.
+struct Scale {
+    x0: f64,
+    y0: f64,
+    k: f64,
+    height: f64,
+}
+
+impl Scale {
+    fn new(map: &Map) -> Scale {
+        let ((xmin, ymin), (xmax, ymax)) = bounding_box(&map.sites);
+        let (w, h) = ((xmax - xmin).max(1e-9), (ymax - ymin).max(1e-9));
+        let inner = MAP_WIDTH - 2.0 * PADDING;
+        let height = (inner * h / w + 2.0 * PADDING).max(MIN_MAP_HEIGHT).min(MAX_MAP_HEIGHT);
+        Scale {
+            x0: xmin,
+            y0: ymin,
+            k: (inner / w).min((height - 2.0 * PADDING) / h),
+            height,
+        }
+    }
+
+    fn point(&self, site: &Site) -> (f64, f64) {
+        (PADDING + (site.x - self.x0) * self.k, PADDING + (site.y - self.y0) * self.k)
+    }
+}
+
+// A straight line, or a curve for the lane-th of several parallel rivers.
+fn river_path(a: (f64, f64), b: (f64, f64), lane: usize, lanes: usize) -> String {
+    if lanes <= 1 {
+        return format!("M{:.1},{:.1}L{:.1},{:.1}", a.0, a.1, b.0, b.1);
+    }
+    let offset = (lane as f64 - (lanes - 1) as f64 / 2.0) * LANE_OFFSET * 2.0;
+    let length = ((b.0 - a.0).powi(2) + (b.1 - a.1).powi(2)).sqrt().max(1e-9);
+    let cx = (a.0 + b.0) / 2.0 - (b.1 - a.1) / length * offset;
+    let cy = (a.1 + b.1) / 2.0 + (b.0 - a.0) / length * offset;
+    format!("M{:.1},{:.1}Q{:.1},{:.1} {:.1},{:.1}", a.0, a.1, cx, cy, b.0, b.1)
+}
+
+// Renders the position after the first `turn` moves as SVG. `futures` are indexed by punter and may be empty.
+pub fn render_svg(map: &Map, moves: &[Move], futures: &[Vec<Future>], turn: usize) -> String {
+    let turn = cmp::min(turn, moves.len());
+    let game: Game = arena::replay(map, moves, turn);
+    let punters = cmp::max(game.punters(), futures.len());
+    let scale = Scale::new(map);
+    let sites: HashMap<SiteId, &Site> = map.sites.iter().map(|s| (s.id, s)).collect();
+    let point = |id: SiteId| scale.point(sites[&id]);
+
+    let mut svg = String::new();
+    writeln!(
+        svg,
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{0}\" height=\"{1:.0}\" viewBox=\"0 0 {0} {1:.0}\" \
+         font-family=\"sans-serif\" font-size=\"14\">",
+        MAP_WIDTH + LEGEND_WIDTH,
+        scale.height
+    ).unwrap();
+    writeln!(svg, "<rect width=\"100%\" height=\"100%\" fill=\"white\"/>").unwrap();
+
+    // Rivers, with claimed ones on top of free ones.
+    let key = |r: &River| (cmp::min(r.source, r.target), cmp::max(r.source, r.target));
+    let mut lanes: HashMap<(SiteId, SiteId), usize> = HashMap::new();
+    for river in map.rivers.iter() {
+        *lanes.entry(key(river)).or_insert(0) += 1;
+    }
+    let mut next_lane: HashMap<(SiteId, SiteId), usize> = HashMap::new();
+    let paths = map.rivers
+        .iter()
+        .map(|river| {
+            let lane = next_lane.entry(key(river)).or_insert(0);
+            *lane += 1;
+            let (s, t) = key(river);
+            river_path(point(s), point(t), *lane - 1, lanes[&key(river)])
+        })
+        .collect::<Vec<_>>();
+    writeln!(svg, "<g fill=\"none\" stroke-linecap=\"round\">").unwrap();
+    for (path, e) in paths.iter().zip(game.edges().iter()) {
+        if e.is_empty() {
+            writeln!(svg, "<path d=\"{}\" stroke=\"#999\" stroke-width=\"1\" stroke-opacity=\"0.5\"/>", path).unwrap();
+        }
+    }
+    for (path, e) in paths.iter().zip(game.edges().iter()) {
+        match e.claimed {
+            Claimed::NotYet => {}
+            Claimed::Claimed(p) => {
+                writeln!(svg, "<path d=\"{}\" stroke=\"{}\" stroke-width=\"3\"/>", path, punter_color(p)).unwrap();
+            }
+            Claimed::Optioned(p, q) => {
+                // The option holder is drawn as a dashed line over the owner.
+                writeln!(svg, "<path d=\"{}\" stroke=\"{}\" stroke-width=\"4\"/>", path, punter_color(p)).unwrap();
+                writeln!(
+                    svg,
+                    "<path d=\"{}\" stroke=\"{}\" stroke-width=\"2\" stroke-dasharray=\"4,4\"/>",
+                    path,
+                    punter_color(q)
+                ).unwrap();
+            }
+        }
+    }
+    writeln!(svg, "</g>").unwrap();
+
+    // The last move is highlighted.
+    if let Some(&Move::Claim { ref claim }) = moves[..turn].last() {
+        let (a, b) = (point(claim.source), point(claim.target));
+        writeln!(
+            svg,
+            "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"black\" stroke-width=\"6\" \
+             stroke-opacity=\"0.3\"/>",
+            a.0,
+            a.1,
+            b.0,
+            b.1
+        ).unwrap();
+    }
+
+    // Futures are dashed lines from mines to their targets, which are squares.
+    for (p, futures) in futures.iter().enumerate() {
+        for future in futures.iter().filter(|f| sites.contains_key(&f.source) && sites.contains_key(&f.target)) {
+            let (a, b) = (point(future.source), point(future.target));
+            writeln!(
+                svg,
+                "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"{}\" stroke-width=\"1.5\" \
+                 stroke-dasharray=\"6,4\"/>",
+                a.0,
+                a.1,
+                b.0,
+                b.1,
+                punter_color(p)
+            ).unwrap();
+            writeln!(
+                svg,
+                "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"10\" height=\"10\" fill=\"white\" stroke=\"{}\" stroke-width=\"2\"/>",
+                b.0 - 5.0,
+                b.1 - 5.0,
+                punter_color(p)
+            ).unwrap();
+        }
+    }
+
+    writeln!(svg, "<g fill=\"#555\">").unwrap();
+    for site in map.sites.iter() {
+        let (x, y) = scale.point(site);
+        writeln!(svg, "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"2\"/>", x, y).unwrap();
+    }
+    writeln!(svg, "</g>").unwrap();
+    writeln!(svg, "<g fill=\"red\" stroke=\"white\" stroke-width=\"2\">").unwrap();
+    for mine in map.mines.iter().filter(|m| sites.contains_key(m)) {
+        let (x, y) = point(*mine);
+        writeln!(svg, "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"7\"/>", x, y).unwrap();
+    }
+    writeln!(svg, "</g>").unwrap();
+
+    // Legend
+    let x = MAP_WIDTH + 10.0;
+    writeln!(svg, "<text x=\"{}\" y=\"30\" font-weight=\"bold\">turn {} / {}</text>", x, turn, moves.len()).unwrap();
+    for p in 0..punters {
+        let score = game.score(p) + futures.get(p).map_or(0, |f| game.futures_score(p, f));
+        let y = 60.0 + 24.0 * p as f64;
+        writeln!(
+            svg,
+            "<rect x=\"{}\" y=\"{}\" width=\"16\" height=\"16\" fill=\"{}\"/>",
+            x,
+            y - 13.0,
+            punter_color(p)
+        ).unwrap();
+        writeln!(svg, "<text x=\"{}\" y=\"{}\">punter {}: {}</text>", x + 24.0, y, p, score).unwrap();
+    }
+    writeln!(svg, "</svg>").unwrap();
+    svg
+}
+
+// Renders a recorded battle after `turns` moves (all moves by default).
+pub fn render_run<P: AsRef<Path>>(input: P, turns: Option<usize>) -> PunterResult<String> {
+    let vis_graph: VisGraph = serde_json::from_reader(fs::File::open(input)?)?;
+    let turns = turns.unwrap_or(vis_graph.moves.len());
+    Ok(render_svg(&vis_graph.map, &vis_graph.moves, &vis_graph.futures, turns))
+}
+
+#[test]
+fn render_svg_test() {
+    let map = arena::read_map(&arena::builtin_map_path("sample.json")).unwrap();
+    let claim = |punter, source, target| {
+        Move::Claim {
+            claim: Claim {
+                punter,
+                source,
+                target,
+            },
+        }
+    };
+    let moves = vec![claim(0, 0, 1), claim(1, 5, 6), claim(0, 1, 2)];
+    let futures = vec![vec![Future { source: 1, target: 4 }], vec![]];
+
+    let svg = render_svg(&map, &moves, &futures, 2);
+    assert!(svg.starts_with("<svg"));
+    assert!(svg.contains("turn 2 / 3"));
+    // Punter 0 has 0-1 (1) but not the future 1-4 (-2^3).
+    assert!(svg.contains("punter 0: -7"));
+    assert!(svg.contains("punter 1: 1"));
+    assert_eq!(svg.matches(&format!("stroke=\"{}\" stroke-width=\"3\"", punter_color(0))).count(), 1);
+    assert_eq!(bounding_box(&map.sites), ((0.0, -2.0), (2.0, 0.0)));
+}
@@ -0,0 +1,308 @@
+use punter::arena::{Listener, Pacer};
+use punter::game::{Claimed, Game};
+use punter::prelude::*;
+use punter::protocol::*;
+use punter::svg;
+use std::cmp;
+use std::collections::HashMap;
+use std::env;
+use std::io::{self, BufRead, Write};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+const LAST_MOVES: usize = 6;
+// ANSI foreground colors. Punters beyond these share colors but keep their own letters.
+const COLORS: [&'static str; 6] = ["31", "32", "33", "34", "35", "36"];
+
+pub enum Pace {
+    // Waits for enter after each turn.
+    Step,
+    FastForward(Duration),
+}
+
+// Slows the shown battle down. It runs outside the listener lock, so the other battles are not held up.
+struct ViewPacer {
+    // The battle being shown, and whether it is still watched.
+    shown: Mutex<(Option<usize>, bool)>,
+    // Locked while waiting, only by the shown battle.
+    pace: Mutex<Pace>,
+}
+
+impl ViewPacer {
+    fn is_shown(&self, battle: usize) -> bool {
+        let shown = self.shown.lock().unwrap();
+        shown.1 && shown.0 == Some(battle)
+    }
+}
+
+impl Pacer for ViewPacer {
+    fn pace(&self, battle: usize) {
+        if !self.is_shown(battle) {
+            return;
+        }
+        let mut pace = self.pace.lock().unwrap();
+        match *pace {
+            Pace::Step => {
+                print!("enter: next turn, f: fast-forward, q: stop watching > ");
+                io::stdout().flush().unwrap();
+                let mut input = String::new();
+                let stdin = io::stdin();
+                match stdin.lock().read_line(&mut input) {
+                    Ok(0) | Err(_) => *pace = Pace::FastForward(Duration::from_millis(0)),
+                    Ok(_) => {
+                        match input.trim() {
+                            "f" => *pace = Pace::FastForward(Duration::from_millis(50)),
+                            "q" => self.shown.lock().unwrap().1 = false,
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            Pace::FastForward(delay) => thread::sleep(delay),
+        }
+    }
+}
+
+// Shows one battle in the terminal: an ASCII map, scores and the last moves.
+// The first battle to start is shown, and the others are ignored.
+pub struct LiveView {
+    width: usize,
+    height: usize,
+    color: bool,
+    pacer: Arc<ViewPacer>,
+    map: Map,
+    names: Vec<String>,
+    game: Option<Game>,
+    moves: Vec<Move>,
+    // Frames are written to stdout.
+    out: Box<Write + Send>,
+}
+
+fn punter_char(p: PunterId) -> char {
+    const CHARS: &'static [u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    CHARS[p % CHARS.len()] as char
+}
+
+fn describe(m: &Move) -> String {
+    match *m {
+        Move::Claim { ref claim } => format!("claim {}-{}", claim.source, claim.target),
+        Move::Option_ { ref option } => format!("option {}-{}", option.source, option.target),
+        Move::Splurge { ref splurge } => {
+            format!("splurge {}", splurge.route.iter().map(|s| s.to_string()).collect::<Vec<_>>().join("-"))
+        }
+        Move::Pass { .. } => "pass".to_string(),
+    }
+}
+
+impl LiveView {
+    // The size is taken from $COLUMNS and $LINES if they are exported.
+    pub fn new(pace: Pace) -> LiveView {
+        let size = |var, default| env::var(var).ok().and_then(|v| v.parse().ok()).unwrap_or(default);
+        let width: usize = size("COLUMNS", 100);
+        let lines: usize = size("LINES", 40);
+        LiveView::with_size(cmp::max(width, 20), cmp::max(lines, 20 + LAST_MOVES) - 6 - LAST_MOVES, pace)
+    }
+
+    // The map is drawn in a width x height area, not counting scores and moves.
+    pub fn with_size(width: usize, height: usize, pace: Pace) -> LiveView {
+        LiveView {
+            width,
+            height,
+            color: true,
+            pacer: Arc::new(ViewPacer {
+                shown: Mutex::new((None, true)),
+                pace: Mutex::new(pace),
+            }),
+            map: Default::default(),
+            names: vec![],
+            game: None,
+            moves: vec![],
+            out: Box::new(io::stdout()),
+        }
+    }
+
+    fn paint(&self, c: char, p: Option<PunterId>) -> String {
+        match p {
+            Some(p) if self.color => format!("\x1b[{}m{}\x1b[0m", COLORS[p % COLORS.len()], c),
+            _ => c.to_string(),
+        }
+    }
+
+    fn draw_map(&self, game: &Game) -> Vec<String> {
+        let ((x0, y0), (x1, y1)) = svg::bounding_box(&self.map.sites);
+        let (dx, dy) = ((x1 - x0).max(1e-9), (y1 - y0).max(1e-9));
+        // A terminal cell is about twice as high as it is wide.
+        let k = ((self.width - 1) as f64 / dx).min(2.0 * (self.height - 1) as f64 / dy);
+        let rows = cmp::min(self.height, (dy * k / 2.0) as usize + 1);
+        let cells: HashMap<SiteId, (usize, usize)> = self.map
+            .sites
+            .iter()
+            .map(|s| (s.id, (((s.x - x0) * k).round() as usize, ((s.y - y0) * k / 2.0).round() as usize)))
+            .collect();
+
+        let mut canvas = vec![vec![(' ', None); self.width]; rows];
+        {
+            let mut line = |a: (usize, usize), b: (usize, usize), c: char, p: Option<PunterId>| {
+                let steps = cmp::max(
+                    (a.0 as i64 - b.0 as i64).abs(),
+                    (a.1 as i64 - b.1 as i64).abs(),
+                ) as usize;
+                for i in 0..steps + 1 {
+                    let t = if steps == 0 { 0.0 } else { i as f64 / steps as f64 };
+                    let col = (a.0 as f64 + (b.0 as f64 - a.0 as f64) * t).round() as usize;
+                    let row = (a.1 as f64 + (b.1 as f64 - a.1 as f64) * t).round() as usize;
+                    if row < rows && col < self.width {
+                        canvas[row][col] = (c, p);
+                    }
+                }
+            };
+            // Free rivers first so that claims are drawn on top of them.
+            let rivers = self.map.rivers.iter().zip(game.edges().iter());
+            for (river, _) in rivers.clone().filter(|&(_, e)| e.is_empty()) {
+                line(cells[&river.source], cells[&river.target], '.', None);
+            }
+            for (river, e) in rivers {
+                match e.claimed {
+                    Claimed::NotYet => {}
+                    Claimed::Claimed(p) => line(cells[&river.source], cells[&river.target], punter_char(p), Some(p)),
+                    Claimed::Optioned(_, q) => line(cells[&river.source], cells[&river.target], punter_char(q), Some(q)),
+                }
+            }
+        }
+        for mine in self.map.mines.iter() {
+            if let Some(&(col, row)) = cells.get(mine) {
+                if row < rows && col < self.width {
+                    canvas[row][col] = ('@', None);
+                }
+            }
+        }
+        canvas
+            .iter()
+            .map(|row| row.iter().map(|&(c, p)| self.paint(c, p)).collect::<String>().trim_right().to_string())
+            .collect()
+    }
+
+    // The whole screen, without clearing codes.
+    pub fn frame(&self) -> String {
+        let game = match self.game {
+            Some(ref game) => game,
+            None => return String::new(),
+        };
+        let mut lines = vec![
+            format!(
+                "battle {}  turn {} / {}",
+                self.pacer.shown.lock().unwrap().0.unwrap_or(0),
+                self.moves.len(),
+                self.map.rivers.len()
+            ),
+        ];
+        lines.extend(self.draw_map(game));
+        lines.push(String::new());
+        for (p, name) in self.names.iter().enumerate() {
+            lines.push(format!(
+                "{} {:>8}  {}",
+                self.paint(punter_char(p), Some(p)),
+                game.score(p),
+                name
+            ));
+        }
+        lines.push(String::new());
+        let skip = self.moves.len().saturating_sub(LAST_MOVES);
+        for (i, m) in self.moves.iter().enumerate().skip(skip) {
+            lines.push(format!("{:>6}: {} {}", i + 1, self.paint(punter_char(m.punter()), Some(m.punter())), describe(m)));
+        }
+        lines.join("\n")
+    }
+
+    fn show(&mut self) {
+        let frame = self.frame();
+        write!(self.out, "\x1b[H\x1b[2J{}\n", frame).unwrap();
+        self.out.flush().unwrap();
+    }
+
+    fn is_shown(&self, battle: usize) -> bool {
+        self.pacer.is_shown(battle)
+    }
+}
+
+impl Listener for LiveView {
+    fn start(&mut self, battle: usize, map: &Map, names: &[String]) {
+        {
+            let mut shown = self.pacer.shown.lock().unwrap();
+            if shown.0.is_some() {
+                return;
+            }
+            shown.0 = Some(battle);
+        }
+        self.map = map.clone();
+        self.names = names.to_vec();
+        self.game = Some(
+            SetupSP {
+                punter: 0,
+                punters: names.len(),
+                map: map.clone(),
+                settings: None,
+            }.into(),
+        );
+        self.show();
+    }
+
+    fn play(&mut self, battle: usize, mov: &Move) {
+        if !self.is_shown(battle) {
+            return;
+        }
+        if let Some(ref mut game) = self.game {
+            game.apply_move(mov.clone());
+        }
+        self.moves.push(mov.clone());
+        self.show();
+    }
+
+    fn stop(&mut self, battle: usize, _scores: &[i64]) {
+        if self.is_shown(battle) {
+            self.show();
+        }
+    }
+
+    fn pacer(&self) -> Option<Arc<Pacer>> {
+        Some(self.pacer.clone())
+    }
+}
+
+#[test]
+fn frame_test() {
+    use punter::arena;
+    use std::time::Instant;
+
+    let map = arena::read_map(&arena::builtin_map_path("sample.json")).unwrap();
+    let quiet = |pace| {
+        let mut view = LiveView::with_size(40, 12, pace);
+        view.color = false;
+        view.out = Box::new(io::sink());
+        view
+    };
+    let mut view = quiet(Pace::FastForward(Duration::from_millis(0)));
+    view.start(3, &map, &["a".to_string(), "b".to_string()]);
+    view.start(4, &map, &["c".to_string(), "d".to_string()]);
+    view.play(3, &Move::from(Claim { punter: 1, source: 5, target: 6 }));
+    view.play(4, &Move::from(Claim { punter: 0, source: 0, target: 1 }));
+
+    let frame = view.frame();
+    assert!(frame.starts_with("battle 3  turn 1 / 12"));
+    assert!(frame.contains("1        1  b"));
+    assert!(frame.contains("     1: 1 claim 5-6"));
+    assert_eq!(frame.matches('@').count(), 2);
+    assert!(!frame.contains("0 claim"));
+
+    // Only the shown battle is slowed down, by its pacer rather than by play().
+    let mut view = quiet(Pace::FastForward(Duration::from_millis(200)));
+    view.start(3, &map, &["a".to_string(), "b".to_string()]);
+    let pacer = view.pacer().unwrap();
+    let start = Instant::now();
+    view.play(3, &Move::from(Claim { punter: 1, source: 5, target: 6 }));
+    pacer.pace(4);
+    assert!(start.elapsed() < Duration::from_millis(200));
+    pacer.pace(3);
+    assert!(start.elapsed() >= Duration::from_millis(200));
+}
@@ -0,0 +1,155 @@
+use punter::arena::{self, Arena, PunterScore};
+use punter::bot::BotMaker;
+use punter::game::Strategy;
+use punter::params::EdgeWeightParams;
+use punter::prelude::*;
+use punter::protocol::*;
+use rand::{Rng, SeedableRng, StdRng};
+use rayon;
+use rayon::prelude::*;
+use serde_json;
+use std::fs;
+use std::io::prelude::*;
+use std::path::PathBuf;
+
+const CANDIDATE: &'static str = "candidate";
+
+// How far one unit of the search space moves each parameter, in the order of EdgeWeightParams::names().
+const SCALES: [f64; 5] = [0.5, 0.2, 0.5, 0.5, 0.5];
+
+// SPSA gains. See Spall, "Implementation of the Simultaneous Perturbation Algorithm for Stochastic
+// Optimization" (1998).
+const GAIN_A: f64 = 1.0;
+const GAIN_C: f64 = 1.0;
+const ALPHA: f64 = 0.602;
+const GAMMA: f64 = 0.101;
+
+// Tunes EdgeWeightParams with SPSA. Each candidate plays against a fixed pool of opponents, and its fitness
+// is the average rank point normalized to [0, 1].
+pub struct Tuner {
+    pub maps: Vec<PathBuf>,
+    pub opponents: Vec<String>,
+    pub games_per_map: usize,
+    pub iterations: usize,
+    pub seed: u64,
+    pub output: PathBuf,
+    pub initial: EdgeWeightParams,
+}
+
+fn to_search_space(params: &EdgeWeightParams) -> Vec<f64> {
+    params.to_vec().iter().zip(SCALES.iter()).map(|(v, s)| v / s).collect()
+}
+
+fn from_search_space(theta: &[f64]) -> EdgeWeightParams {
+    EdgeWeightParams::from_vec(&theta.iter().zip(SCALES.iter()).map(|(v, s)| v * s).collect::<Vec<_>>())
+}
+
+impl Tuner {
+    fn evaluate(&self, maps: &[Map], opponents: &[Strategy], params: EdgeWeightParams, seed: u64) -> f64 {
+        let mut bot_makers = vec![
+            BotMaker::Parameterised {
+                name: CANDIDATE.to_string(),
+                strategy: Strategy::EdgeWeight,
+                params,
+            },
+        ];
+        bot_makers.extend(opponents.iter().map(|s| BotMaker::Internal(*s)));
+        let punters = bot_makers.len();
+        let arena = Arena {
+            bot_makers,
+            maps: self.maps.clone(),
+            games_per_map: self.games_per_map,
+            punters: None,
+            settings: vec![Default::default()],
+            seed: Some(seed),
+            record_dir: None,
+            report: None,
+            ratings: None,
+            export_dir: None,
+        };
+        let points = maps.par_iter()
+            .enumerate()
+            .flat_map(|(map_index, map)| arena.play_map(map_index, map, None))
+            .filter(|r: &PunterScore| r.bot_name == CANDIDATE)
+            .map(|r| (r.point - 1) as f64 / (punters - 1) as f64)
+            .collect::<Vec<f64>>();
+        points.iter().sum::<f64>() / points.len() as f64
+    }
+
+    pub fn run(&self) -> PunterResult<EdgeWeightParams> {
+        let opponents = self.opponents
+            .iter()
+            .map(|s| s.parse())
+            .collect::<PunterResult<Vec<Strategy>>>()?;
+        if opponents.is_empty() {
+            return Err(PunterError::InvalidArgument("no opponents".to_string()));
+        }
+        let maps = self.maps.iter().map(|p| arena::read_map(p)).collect::<PunterResult<Vec<_>>>()?;
+        let mut rng = StdRng::from_seed(&[self.seed as usize][..]);
+
+        let mut theta = to_search_space(&self.initial);
+        let mut curve = format!(
+            "iteration,fitness_plus,fitness_minus,{}\n",
+            EdgeWeightParams::names().join(",")
+        );
+        let stability = self.iterations as f64 / 10.0;
+        for k in 0..self.iterations {
+            let a_k = GAIN_A / (k as f64 + 1.0 + stability).powf(ALPHA);
+            let c_k = GAIN_C / (k as f64 + 1.0).powf(GAMMA);
+            let delta = theta
+                .iter()
+                .map(|_| if rng.gen() { 1.0 } else { -1.0 })
+                .collect::<Vec<f64>>();
+            let (plus, minus) = {
+                let perturbed = |sign: f64| {
+                    from_search_space(&theta.iter().zip(delta.iter()).map(|(t, d)| t + sign * c_k * d).collect::<Vec<_>>())
+                };
+                (perturbed(1.0), perturbed(-1.0))
+            };
+
+            // Both sides play the same seat orders.
+            let seed = rng.gen::<u64>();
+            let (f_plus, f_minus) = rayon::join(
+                || self.evaluate(&maps, &opponents, plus, seed),
+                || self.evaluate(&maps, &opponents, minus, seed),
+            );
+            for (t, d) in theta.iter_mut().zip(delta.iter()) {
+                *t += a_k * (f_plus - f_minus) / (2.0 * c_k * d);
+            }
+
+            let current = from_search_space(&theta);
+            info!("iteration {}: {:.3} / {:.3}, params: {:?}", k, f_plus, f_minus, current);
+            curve.push_str(&format!(
+                "{},{},{},{}\n",
+                k,
+                f_plus,
+                f_minus,
+                current.to_vec().iter().map(|v| v.to_string()).collect::<Vec<_>>().join(",")
+            ));
+        }
+
+        // The final iterate, rather than the luckiest of the noisy evaluations on the way. It is compared with the
+        // initial parameters on games which played no part in tuning.
+        let tuned = from_search_space(&theta);
+        let seed = rng.gen::<u64>();
+        let (f_initial, f_tuned) = rayon::join(
+            || self.evaluate(&maps, &opponents, self.initial, seed),
+            || self.evaluate(&maps, &opponents, tuned, seed),
+        );
+
+        fs::create_dir_all(&self.output)?;
+        let mut f = fs::File::create(self.output.join("best_params.json"))?;
+        f.write_all(serde_json::to_string_pretty(&tuned)?.as_bytes())?;
+        let mut f = fs::File::create(self.output.join("learning_curve.csv"))?;
+        f.write_all(curve.as_bytes())?;
+        println!("fitness on fresh games: {:.3} (initial: {:.3}), params: {:?}", f_tuned, f_initial, tuned);
+        Ok(tuned)
+    }
+}
+
+pub fn default_maps() -> Vec<PathBuf> {
+    ["lambda.json", "circle.json", "randomMedium.json"]
+        .iter()
+        .map(|p| arena::builtin_map_path(p))
+        .collect()
+}
@@ -0,0 +1,197 @@
+use punter::prelude::*;
+use punter::protocol::*;
+use serde_json;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MapProblem {
+    DuplicateSite(SiteId),
+    // A river or a mine refers to a site which is not in the map.
+    MissingSite(SiteId),
+    SelfLoop(SiteId),
+    // Parallel rivers are playable, but rare enough to be a mistake.
+    DuplicateRiver(SiteId, SiteId),
+    DuplicateMine(SiteId),
+    // A mine from which no other mine can be reached.
+    UnreachableMine(SiteId),
+}
+
+impl MapProblem {
+    // Problems which Game can not handle. Others only make the map odd.
+    pub fn is_fatal(&self) -> bool {
+        match *self {
+            MapProblem::DuplicateSite(_) |
+            MapProblem::MissingSite(_) |
+            MapProblem::SelfLoop(_) => true,
+            MapProblem::DuplicateRiver(_, _) |
+            MapProblem::DuplicateMine(_) |
+            MapProblem::UnreachableMine(_) => false,
+        }
+    }
+}
+
+impl fmt::Display for MapProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            MapProblem::DuplicateSite(id) => write!(f, "duplicate site: {}", id),
+            MapProblem::MissingSite(id) => write!(f, "missing site: {}", id),
+            MapProblem::SelfLoop(id) => write!(f, "self-loop river: {}-{}", id, id),
+            MapProblem::DuplicateRiver(s, t) => write!(f, "duplicate river: {}-{}", s, t),
+            MapProblem::DuplicateMine(id) => write!(f, "duplicate mine: {}", id),
+            MapProblem::UnreachableMine(id) => write!(f, "unreachable mine: {}", id),
+        }
+    }
+}
+
+fn river_key(river: &River) -> (SiteId, SiteId) {
+    (cmp::min(river.source, river.target), cmp::max(river.source, river.target))
+}
+
+pub fn validate(map: &Map) -> Vec<MapProblem> {
+    let mut problems = vec![];
+
+    let mut sites = HashSet::new();
+    for site in map.sites.iter() {
+        if !sites.insert(site.id) {
+            problems.push(MapProblem::DuplicateSite(site.id));
+        }
+    }
+
+    let mut missing = HashSet::new();
+    let mut rivers = HashSet::new();
+    for river in map.rivers.iter() {
+        for id in [river.source, river.target].iter() {
+            if !sites.contains(id) && missing.insert(*id) {
+                problems.push(MapProblem::MissingSite(*id));
+            }
+        }
+        if river.source == river.target {
+            problems.push(MapProblem::SelfLoop(river.source));
+        } else if !rivers.insert(river_key(river)) {
+            let (s, t) = river_key(river);
+            problems.push(MapProblem::DuplicateRiver(s, t));
+        }
+    }
+
+    let mut mines = HashSet::new();
+    for mine in map.mines.iter() {
+        if !sites.contains(mine) && missing.insert(*mine) {
+            problems.push(MapProblem::MissingSite(*mine));
+        }
+        if !mines.insert(*mine) {
+            problems.push(MapProblem::DuplicateMine(*mine));
+        }
+    }
+
+    if mines.len() > 1 {
+        let mut adj: HashMap<SiteId, Vec<SiteId>> = HashMap::new();
+        for river in map.rivers.iter() {
+            adj.entry(river.source).or_insert_with(Vec::new).push(river.target);
+            adj.entry(river.target).or_insert_with(Vec::new).push(river.source);
+        }
+        let mut reported = HashSet::new();
+        for mine in map.mines.iter() {
+            if !reported.insert(*mine) {
+                continue;
+            }
+            let mut visited = HashSet::new();
+            visited.insert(*mine);
+            let mut stack = vec![*mine];
+            while let Some(s) = stack.pop() {
+                for t in adj.get(&s).into_iter().flat_map(|ts| ts.iter()) {
+                    if visited.insert(*t) {
+                        stack.push(*t);
+                    }
+                }
+            }
+            if !mines.iter().any(|m| m != mine && visited.contains(m)) {
+                problems.push(MapProblem::UnreachableMine(*mine));
+            }
+        }
+    }
+    problems
+}
+
+// Warns about odd maps and fails on maps which Game can not handle.
+pub fn check(map: &Map) -> PunterResult<()> {
+    let problems = validate(map);
+    for problem in problems.iter().filter(|p| !p.is_fatal()) {
+        warn!("map: {}", problem);
+    }
+    let fatal = problems.iter().filter(|p| p.is_fatal()).map(|p| p.to_string()).collect::<Vec<_>>();
+    if fatal.is_empty() {
+        Ok(())
+    } else {
+        Err(PunterError::InvalidMap(fatal.join(", ")))
+    }
+}
+
+// Drops duplicate sites, rivers and mines, self-loops, and rivers and mines referring to missing sites.
+// Unreachable mines are kept since they are legal.
+pub fn normalize(map: &Map) -> Map {
+    let mut ids = HashSet::new();
+    let sites: Vec<Site> = map.sites.iter().filter(|site| ids.insert(site.id)).cloned().collect();
+    let mut keys = HashSet::new();
+    let rivers: Vec<River> = map.rivers
+        .iter()
+        .filter(|river| {
+            river.source != river.target && ids.contains(&river.source) && ids.contains(&river.target) &&
+                keys.insert(river_key(river))
+        })
+        .cloned()
+        .collect();
+    let mut mine_ids = HashSet::new();
+    let mines: Vec<SiteId> = map.mines.iter().filter(|mine| ids.contains(*mine) && mine_ids.insert(**mine)).cloned().collect();
+    Map { sites, rivers, mines }
+}
+
+// Prints the problems of a map, and writes the normalized map if `output` is given.
+pub fn validate_map_run<P: AsRef<Path>>(path: P, output: Option<&str>) -> PunterResult<()> {
+    let map: Map = serde_json::from_reader(fs::File::open(path)?)?;
+    let problems = validate(&map);
+    for problem in problems.iter() {
+        println!("{}{}", if problem.is_fatal() { "error: " } else { "warning: " }, problem);
+    }
+    if problems.is_empty() {
+        println!("ok");
+    }
+    if let Some(output) = output {
+        serde_json::to_writer(fs::File::create(output)?, &normalize(&map))?;
+    }
+    Ok(())
+}
+
+#[test]
+fn validate_test() {
+    let map = Map {
+        sites: [0, 1, 2, 3, 3, 4].iter().map(|id| Site { id: *id, x: 0.0, y: 0.0 }).collect(),
+        rivers: [(0, 1), (1, 0), (1, 1), (1, 2), (2, 9)]
+            .iter()
+            .map(|&(source, target)| River { source, target })
+            .collect(),
+        mines: vec![0, 2, 2, 4],
+    };
+    assert_eq!(
+        validate(&map),
+        vec![
+            MapProblem::DuplicateSite(3),
+            MapProblem::DuplicateRiver(0, 1),
+            MapProblem::SelfLoop(1),
+            MapProblem::MissingSite(9),
+            MapProblem::DuplicateMine(2),
+            MapProblem::UnreachableMine(4),
+        ]
+    );
+    assert!(check(&map).is_err());
+
+    let normalized = normalize(&map);
+    assert_eq!(normalized.sites.len(), 5);
+    assert_eq!(normalized.rivers.len(), 2);
+    assert_eq!(normalized.mines, vec![0, 2, 4]);
+    assert_eq!(validate(&normalized), vec![MapProblem::UnreachableMine(4)]);
+    assert!(check(&normalized).is_ok());
+}
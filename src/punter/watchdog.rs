@@ -0,0 +1,82 @@
+use punter::budget::TimeBudget;
+use punter::game::{Game, Strategy};
+use punter::protocol::*;
+use std::sync::{Arc, mpsc};
+use std::sync::atomic::{ATOMIC_USIZE_INIT, AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+// How long the watchdog waits for a strategy after its budget is over.
+const GRACE_MILLIS: u64 = 50;
+// Strategies which do not poll their budget can not be cancelled, so the watchdog stops spawning new ones while
+// this many abandoned strategies are still running.
+const MAX_ABANDONED: usize = 2;
+
+// States of a strategy thread.
+const RUNNING: usize = 0;
+const DONE: usize = 1;
+const ABANDONED: usize = 2;
+
+// Strategy threads given up on and still running.
+static ABANDONED_THREADS: AtomicUsize = ATOMIC_USIZE_INIT;
+
+// Runs a strategy on its own thread. If the strategy panics or does not return in time, a cheap fallback
+// move is used instead, so that a bug in a strategy costs one move rather than the whole game.
+pub fn play(game: &Game, strategy: Strategy, budget: &TimeBudget) -> Move {
+    let abandoned = ABANDONED_THREADS.load(Ordering::SeqCst);
+    if abandoned >= MAX_ABANDONED {
+        warn!("watchdog: {} abandoned strategies are still running. Using a fallback move", abandoned);
+        return game.play_fallback();
+    }
+    let (tx, rx) = mpsc::channel();
+    let worker_game = game.clone();
+    let worker_budget = budget.clone();
+    let state = Arc::new(AtomicUsize::new(RUNNING));
+    let worker_state = state.clone();
+    let spawned = thread::Builder::new()
+        .name(format!("{:?}", strategy))
+        .spawn(move || {
+            let mov = worker_game.play(strategy, &worker_budget);
+            if worker_state.swap(DONE, Ordering::SeqCst) == ABANDONED {
+                ABANDONED_THREADS.fetch_sub(1, Ordering::SeqCst);
+            }
+            // The watchdog might have given up on us.
+            let _ = tx.send(mov);
+        });
+    if let Err(e) = spawned {
+        warn!("watchdog: failed to spawn a thread for {:?}: {}", strategy, e);
+        return game.play_fallback();
+    }
+
+    match rx.recv_timeout(budget.remaining() + Duration::from_millis(GRACE_MILLIS)) {
+        Ok(mov) => mov,
+        Err(mpsc::RecvTimeoutError::Timeout) => {
+            warn!("watchdog: {:?} missed the deadline. Using a fallback move", strategy);
+            // Searches poll the budget and stop soon. Other strategies run to the end on their own.
+            budget.cancel();
+            if state.compare_exchange(RUNNING, ABANDONED, Ordering::SeqCst, Ordering::SeqCst).is_ok() {
+                ABANDONED_THREADS.fetch_add(1, Ordering::SeqCst);
+            }
+            game.play_fallback()
+        }
+        Err(mpsc::RecvTimeoutError::Disconnected) => {
+            warn!("watchdog: {:?} panicked. Using a fallback move", strategy);
+            game.play_fallback()
+        }
+    }
+}
+
+#[test]
+fn abandoned_test() {
+    use punter::arena;
+    use serde_json;
+    use std::time::Instant;
+
+    let game = arena::sample_game("lambda.json");
+    ABANDONED_THREADS.store(MAX_ABANDONED, Ordering::SeqCst);
+    let start = Instant::now();
+    let mov = play(&game, Strategy::Minimax, &TimeBudget::new(Duration::from_secs(10)));
+    ABANDONED_THREADS.store(0, Ordering::SeqCst);
+    assert!(start.elapsed() < Duration::from_secs(1));
+    assert_eq!(serde_json::to_string(&mov).unwrap(), serde_json::to_string(&game.play_fallback()).unwrap());
+}